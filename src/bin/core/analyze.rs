@@ -3,26 +3,33 @@ use rustc_borrowck::consumers::{
     BorrowSet, ConsumerOptions, PoloniusInput, PoloniusLocationTable, PoloniusOutput, RichLocation,
     RustcFacts, get_body_with_borrowck_facts,
 };
-use rustc_hir::def_id::LocalDefId;
+use rustc_hir::{
+    def::DefKind,
+    def_id::{LOCAL_CRATE, LocalDefId},
+};
 use rustc_middle::{
     mir::{
-        BasicBlock, BasicBlockData, BasicBlocks, Body, BorrowKind, Local, Operand, Rvalue,
-        StatementKind, TerminatorKind, VarDebugInfoContents,
+        AggregateKind, BasicBlock, BasicBlockData, BasicBlocks, Body, BorrowKind, Local, Location,
+        Operand, Place, ProjectionElem, RETURN_PLACE, Rvalue, StatementKind, TerminatorKind,
+        VarDebugInfoContents,
     },
-    ty::TyCtxt,
+    ty::{self, TyCtxt, UpvarCapture},
 };
-use rustc_span::{Span, source_map::SourceMap};
+use rustc_span::Span;
 use rustowl::{models::*, utils};
-use std::collections::{BTreeSet, HashMap};
+use std::collections::{BTreeMap, BTreeSet, HashMap, VecDeque};
 use std::future::Future;
 use std::hash::Hash;
 use std::pin::Pin;
+use std::sync::Arc;
 
 pub type MirAnalyzeFuture<'tcx> =
     Pin<Box<dyn Future<Output = MirAnalyzer<'tcx>> + Send + Sync + 'tcx>>;
 
 type Borrow = <RustcFacts as FactTypes>::Loan;
 type Region = <RustcFacts as FactTypes>::Origin;
+type Point = <RustcFacts as FactTypes>::Point;
+type MovePath = <RustcFacts as FactTypes>::Path;
 
 trait Append<K, V>
 where
@@ -58,15 +65,94 @@ where
     }
 }
 
-fn range_from_span(source: &str, span: Span, offset: u32) -> Option<Range> {
+/// id of the function in [`FnLocal`], which unlike `local_def_index` differs between
+/// crates, e.g. a library and its test target, as it is derived from the def path hash;
+/// 53 bits are kept to be exact in JavaScript numbers
+fn stable_fn_id(tcx: TyCtxt<'_>, def_id: LocalDefId) -> u64 {
+    let hash = tcx.def_path_hash(def_id.to_def_id());
+    (hash.stable_crate_id().as_u64().rotate_left(32) ^ hash.local_hash().as_u64()) & ((1 << 53) - 1)
+}
+
+fn range_from_span(source: &utils::LineIndex, span: Span, offset: u32) -> Option<Range> {
     let from = Loc::new(source, span.lo().0, offset);
     let until = Loc::new(source, span.hi().0, offset);
     Range::new(from, until)
 }
 
+/// collect names of locals which are bound to user variables
+fn local_names(body: &Body<'_>) -> HashMap<Local, String> {
+    body.var_debug_info
+        .iter()
+        .filter_map(|debug| match &debug.value {
+            VarDebugInfoContents::Place(place) if place.projection.is_empty() => {
+                Some((place.local, debug.name.as_str().to_owned()))
+            }
+            _ => None,
+        })
+        .collect()
+}
+
+/// translate MIR place into place path with field names
+fn mir_place<'tcx>(
+    tcx: TyCtxt<'tcx>,
+    body: &Body<'tcx>,
+    local_names: &HashMap<Local, String>,
+    place: Place<'tcx>,
+) -> MirPlace {
+    let projection = place
+        .iter_projections()
+        .filter_map(|(base, elem)| match elem {
+            ProjectionElem::Deref => Some(MirProjection::Deref),
+            ProjectionElem::Field(field, _) => {
+                let base_ty = base.ty(body, tcx);
+                let name = match base_ty.ty.kind() {
+                    ty::Adt(adt, _) => {
+                        let variant = base_ty
+                            .variant_index
+                            .map(|v| adt.variant(v))
+                            .unwrap_or_else(|| adt.non_enum_variant());
+                        Some(variant.fields[field].name.to_string())
+                    }
+                    ty::Closure(def_id, _) => def_id.as_local().and_then(|def_id| {
+                        tcx.closure_captures(def_id)
+                            .get(field.index())
+                            .map(|capture| capture.to_symbol().to_string())
+                    }),
+                    _ => None,
+                };
+                Some(MirProjection::Field {
+                    index: field.as_u32(),
+                    name: name.unwrap_or_else(|| field.as_u32().to_string()),
+                })
+            }
+            ProjectionElem::Index(_) | ProjectionElem::ConstantIndex { .. } => {
+                Some(MirProjection::Index)
+            }
+            ProjectionElem::Subslice { .. } => Some(MirProjection::Subslice),
+            ProjectionElem::Downcast(name, variant) => {
+                let variant = name.map(|v| v.to_string()).unwrap_or_else(|| {
+                    match base.ty(body, tcx).ty.kind() {
+                        ty::Adt(adt, _) => adt.variant(variant).name.to_string(),
+                        _ => variant.as_u32().to_string(),
+                    }
+                });
+                Some(MirProjection::Downcast { variant })
+            }
+            ProjectionElem::OpaqueCast(_)
+            | ProjectionElem::UnwrapUnsafeBinder(_)
+            | ProjectionElem::Subtype(_) => None,
+        })
+        .collect();
+    let base = local_names
+        .get(&place.local)
+        .cloned()
+        .unwrap_or_else(|| format!("{:?}", place.local));
+    MirPlace::new(&base, projection)
+}
+
 pub struct MirAnalyzer<'tcx> {
     filename: String,
-    source: String,
+    source: Arc<utils::LineIndex>,
     offset: u32,
     location_table: PoloniusLocationTable,
     borrow_set: BorrowSet<'tcx>,
@@ -76,8 +162,15 @@ pub struct MirAnalyzer<'tcx> {
     output_datafrog: PoloniusOutput,
     bb_map: HashMap<BasicBlock, BasicBlockData<'tcx>>,
     borrow_locals: HashMap<Borrow, Local>,
+    borrow_places: HashMap<Borrow, MirPlace>,
+    region_names: HashMap<Region, String>,
     basic_blocks: Vec<MirBasicBlock>,
-    fn_id: LocalDefId,
+    closures: Vec<MirClosure>,
+    fn_id: u64,
+    key: FnKey,
+    path: String,
+    signature: String,
+    span: Range,
 }
 impl MirAnalyzer<'_> {
     /// initialize analyzer
@@ -99,15 +192,65 @@ impl MirAnalyzer<'_> {
             &rustc_span::RealFileName::LocalPath(std::env::current_dir().unwrap()),
         );
         let path = filename.to_path(rustc_span::FileNameDisplayPreference::Local);
-        let source = std::fs::read_to_string(path).unwrap();
+        let source = super::line_index(path);
         let filename = path.to_string_lossy().to_string();
         log::info!("facts of {fn_id:?} prepared; start analyze of {fn_id:?}");
 
         // local -> all borrows on that local
         let mut borrow_locals = HashMap::new();
+        // borrow -> borrowed place
+        let mut borrow_places = HashMap::new();
+        let local_names = local_names(&body);
         for (local, borrow_idc) in borrow_set.local_map().iter() {
             for borrow_idx in borrow_idc {
                 borrow_locals.insert(*borrow_idx, *local);
+                borrow_places.insert(
+                    *borrow_idx,
+                    mir_place(
+                        tcx,
+                        &body,
+                        &local_names,
+                        borrow_set[*borrow_idx].borrowed_place(),
+                    ),
+                );
+            }
+        }
+
+        // names of regions in the signature, e.g. `'a`
+        let mut region_names = HashMap::new();
+        if matches!(tcx.def_kind(fn_id), DefKind::Fn | DefKind::AssocFn) {
+            let sig = tcx.fn_sig(fn_id).instantiate_identity().skip_binder();
+            for (local, sig_ty) in body
+                .args_iter()
+                .chain([RETURN_PLACE])
+                .zip(sig.inputs_and_output)
+            {
+                let regions: Vec<_> = body.local_decls[local]
+                    .ty
+                    .walk()
+                    .filter_map(|v| v.as_region())
+                    .collect();
+                let named: Vec<_> = sig_ty.walk().filter_map(|v| v.as_region()).collect();
+                // normalized types in the body may differ from the signature
+                if regions.len() != named.len() {
+                    continue;
+                }
+                for (region, named) in regions.into_iter().zip(named) {
+                    if let (ty::ReVar(vid), Some(name)) = (region.kind(), named.get_name()) {
+                        region_names.insert(Region::from(vid), name.to_string());
+                    }
+                }
+            }
+            // regions of the arguments are equal to the universal regions they are named after
+            let named: Vec<_> = region_names.clone().into_iter().collect();
+            for universal in input.universal_region.iter() {
+                if let Some((_, name)) = named.iter().find(|(vid, _)| {
+                    facts
+                        .region_inference_context
+                        .eval_equal((*universal).into(), (*vid).into())
+                }) {
+                    region_names.insert(*universal, name.clone());
+                }
             }
         }
 
@@ -118,14 +261,48 @@ impl MirAnalyzer<'_> {
             .iter_enumerated()
             .map(|(b, d)| (b, d.clone()))
             .collect();
+        let id = stable_fn_id(tcx, fn_id);
         let basic_blocks = Self::basic_blocks(
-            fn_id,
+            tcx,
+            id,
             &source,
             offset,
             &facts.body.basic_blocks,
-            tcx.sess.source_map(),
+            |place| mir_place(tcx, &body, &local_names, place),
+        );
+        let closures = Self::closures(
+            tcx,
+            id,
+            &source,
+            offset,
+            &facts.body.basic_blocks,
+            |place| mir_place(tcx, &body, &local_names, place),
         );
 
+        let key = FnKey {
+            krate: tcx.crate_name(LOCAL_CRATE).to_string(),
+            def_path_hash: tcx.def_path_hash(fn_id.to_def_id()).0.to_hex(),
+        };
+        let path = tcx.def_path_str(fn_id);
+        // functions whose span is unknown are regarded as covering the whole file
+        let span = range_from_span(&source, facts.body.span, offset)
+            .or_else(|| Range::new(Loc(0), Loc(source.len())))
+            .unwrap_or(Range::new(Loc(0), Loc(1)).unwrap());
+        let signature = match tcx.def_kind(fn_id) {
+            DefKind::Fn | DefKind::AssocFn => tcx.fn_sig(fn_id).instantiate_identity().to_string(),
+            DefKind::Closure => format!(
+                "|{}| -> {}",
+                // the first argument is the closure itself
+                body.args_iter()
+                    .skip(1)
+                    .map(|arg| body.local_decls[arg].ty.to_string())
+                    .collect::<Vec<_>>()
+                    .join(", "),
+                body.return_ty()
+            ),
+            _ => body.return_ty().to_string(),
+        };
+
         Box::pin(async move {
             log::info!("start re-computing borrow check with dump: true");
             // compute insensitive
@@ -152,8 +329,15 @@ impl MirAnalyzer<'_> {
                 output_datafrog,
                 bb_map,
                 borrow_locals,
+                borrow_places,
+                region_names,
                 basic_blocks,
-                fn_id,
+                closures,
+                fn_id: id,
+                key,
+                path,
+                signature,
+                span,
             }
         })
     }
@@ -173,6 +357,27 @@ impl MirAnalyzer<'_> {
             })
             .and_then(|span| range_from_span(&self.source, span, self.offset))
     }
+    fn point_to_range(&self, point: Point) -> Option<Range> {
+        match self.location_table.to_rich_location(point) {
+            RichLocation::Start(l) | RichLocation::Mid(l) => {
+                self.stmt_location_to_range(l.block, l.statement_index)
+            }
+        }
+    }
+    /// storage markers are counted as moves by move paths, though users never see them
+    fn is_storage_marker(&self, point: Point) -> bool {
+        let (RichLocation::Start(l) | RichLocation::Mid(l)) =
+            self.location_table.to_rich_location(point);
+        self.bb_map
+            .get(&l.block)
+            .and_then(|bb| bb.statements.get(l.statement_index))
+            .is_some_and(|stmt| {
+                matches!(
+                    stmt.kind,
+                    StatementKind::StorageLive(_) | StatementKind::StorageDead(_)
+                )
+            })
+    }
     fn rich_locations_to_ranges(&self, locations: &[RichLocation]) -> Vec<Range> {
         let mut starts = Vec::new();
         let mut mids = Vec::new();
@@ -219,12 +424,55 @@ impl MirAnalyzer<'_> {
             .map(|(local, locations)| {
                 (
                     local,
-                    utils::eliminated_ranges(self.rich_locations_to_ranges(&locations)),
+                    RangeSet::from(self.rich_locations_to_ranges(&locations)).into_vec(),
                 )
             })
             .collect()
     }
 
+    /// obtain map from local id to suspension points (`.await` or `yield`) it is live across
+    fn held_across_yield(&self) -> HashMap<Local, Vec<Range>> {
+        let mut held = HashMap::new();
+        for bb in self.bb_map.values() {
+            let terminator = bb.terminator();
+            let TerminatorKind::Yield {
+                resume, resume_arg, ..
+            } = &terminator.kind
+            else {
+                continue;
+            };
+            let Some(range) =
+                range_from_span(&self.source, terminator.source_info.span, self.offset)
+            else {
+                continue;
+            };
+            // locals live when resumed must be kept in the coroutine state while suspended
+            let resumed = self.location_table.start_index(Location {
+                block: *resume,
+                statement_index: 0,
+            });
+            let locals: BTreeSet<_> = self
+                .output_datafrog
+                .var_live_on_entry
+                .get(&resumed)
+                .into_iter()
+                .chain(self.output_datafrog.var_drop_live_on_entry.get(&resumed))
+                .flatten()
+                // arguments of coroutine body are the coroutine itself and the resume argument
+                .filter(|local| self.body.arg_count < local.as_usize())
+                .filter(|local| **local != resume_arg.local)
+                .copied()
+                .collect();
+            for local in locals {
+                held.append(&local, range);
+            }
+        }
+        for ranges in held.values_mut() {
+            ranges.sort_by_key(|range| range.from());
+        }
+        held
+    }
+
     /// collect user defined variables from debug info in MIR
     fn collect_user_vars(&self) -> HashMap<Local, (Range, String)> {
         self.body
@@ -243,23 +491,25 @@ impl MirAnalyzer<'_> {
     fn collect_decls(&self) -> Vec<MirDecl> {
         let user_vars = self.collect_user_vars();
         let lives = self.get_accurate_live();
-        let (shared, mutable) = self.get_borrow_live();
+        let place_borrows = self.get_place_borrows();
         let must_live_at = self.get_must_live();
         let drop_range = self.drop_range();
+        let held_across_await = self.held_across_yield();
         self.body
             .local_decls
             .iter_enumerated()
             .map(|(local, decl)| {
                 let ty = decl.ty.to_string();
-                let must_live_at = utils::eliminated_ranges(
-                    must_live_at.get(&local).cloned().unwrap_or(Vec::new()),
-                );
+                let (must_live_at, must_live_blame) =
+                    must_live_at.get(&local).cloned().unwrap_or_default();
+                let must_live_at = RangeSet::from(must_live_at).into_vec();
                 let lives = lives.get(&local).cloned().unwrap_or(Vec::new());
-                let shared_borrow = shared.get(&local).cloned().unwrap_or(Vec::new());
-                let mutable_borrow = mutable.get(&local).cloned().unwrap_or(Vec::new());
+                let place_borrows = place_borrows.get(&local).cloned().unwrap_or(Vec::new());
                 let drop = self.is_drop(local);
                 let drop_range = drop_range.get(&local).cloned().unwrap_or(Vec::new());
-                let fn_local = FnLocal::new(local.as_u32(), self.fn_id.local_def_index.as_u32());
+                let held_across_await =
+                    held_across_await.get(&local).cloned().unwrap_or(Vec::new());
+                let fn_local = FnLocal::new(local.as_u32(), self.fn_id);
                 if let Some((span, name)) = user_vars.get(&local).cloned() {
                     MirDecl::User {
                         local: fn_local,
@@ -267,22 +517,24 @@ impl MirAnalyzer<'_> {
                         span,
                         ty,
                         lives,
-                        shared_borrow,
-                        mutable_borrow,
+                        place_borrows,
                         must_live_at,
+                        must_live_blame,
                         drop,
                         drop_range,
+                        held_across_await,
                     }
                 } else {
                     MirDecl::Other {
                         local: fn_local,
                         ty,
                         lives,
-                        shared_borrow,
-                        mutable_borrow,
+                        place_borrows,
                         drop,
                         drop_range,
                         must_live_at,
+                        must_live_blame,
+                        held_across_await,
                     }
                 }
             })
@@ -290,13 +542,22 @@ impl MirAnalyzer<'_> {
     }
 
     /// collect and translate basic blocks
-    fn basic_blocks(
-        fn_id: LocalDefId,
-        source: &str,
+    fn basic_blocks<'tcx>(
+        tcx: TyCtxt<'tcx>,
+        fn_id: u64,
+        source: &utils::LineIndex,
         offset: u32,
-        basic_blocks: &BasicBlocks<'_>,
-        source_map: &SourceMap,
+        basic_blocks: &BasicBlocks<'tcx>,
+        mir_place: impl Fn(Place<'tcx>) -> MirPlace,
     ) -> Vec<MirBasicBlock> {
+        let source_map = tcx.sess.source_map();
+        // `Clone::clone`, but not `Clone::clone_from`
+        let is_clone = |func: &Operand<'tcx>| {
+            func.const_fn_def().is_some_and(|(def_id, _)| {
+                tcx.trait_of_item(def_id) == tcx.lang_items().clone_trait()
+                    && tcx.item_name(def_id) == rustc_span::sym::clone
+            })
+        };
         basic_blocks
             .iter_enumerated()
             .map(|(b, d)| (b, d.clone()))
@@ -317,10 +578,8 @@ impl MirAnalyzer<'_> {
                                         let local = p.local;
                                         range_from_span(source, statement.source_info.span, offset)
                                             .map(|range| MirRval::Move {
-                                                target_local: FnLocal::new(
-                                                    local.as_u32(),
-                                                    fn_id.local_def_index.as_u32(),
-                                                ),
+                                                target_local: FnLocal::new(local.as_u32(), fn_id),
+                                                target_place: mir_place(*p),
                                                 range,
                                             })
                                     }
@@ -330,10 +589,8 @@ impl MirAnalyzer<'_> {
                                         let outlive = None;
                                         range_from_span(source, statement.source_info.span, offset)
                                             .map(|range| MirRval::Borrow {
-                                                target_local: FnLocal::new(
-                                                    local.as_u32(),
-                                                    fn_id.local_def_index.as_u32(),
-                                                ),
+                                                target_local: FnLocal::new(local.as_u32(), fn_id),
+                                                target_place: mir_place(*place),
                                                 range,
                                                 mutable,
                                                 outlive,
@@ -343,10 +600,7 @@ impl MirAnalyzer<'_> {
                                 };
                                 range_from_span(source, statement.source_info.span, offset).map(
                                     |range| MirStatement::Assign {
-                                        target_local: FnLocal::new(
-                                            target_local_index,
-                                            fn_id.local_def_index.as_u32(),
-                                        ),
+                                        target_local: FnLocal::new(target_local_index, fn_id),
                                         range,
                                         rval: rv,
                                     },
@@ -364,15 +618,13 @@ impl MirAnalyzer<'_> {
                             TerminatorKind::Drop { place, .. } => {
                                 range_from_span(source, terminator.source_info.span, offset).map(
                                     |range| MirTerminator::Drop {
-                                        local: FnLocal::new(
-                                            place.local.as_u32(),
-                                            fn_id.local_def_index.as_u32(),
-                                        ),
+                                        local: FnLocal::new(place.local.as_u32(), fn_id),
                                         range,
                                     },
                                 )
                             }
                             TerminatorKind::Call {
+                                func,
                                 destination,
                                 fn_span,
                                 ..
@@ -380,9 +632,10 @@ impl MirAnalyzer<'_> {
                                 MirTerminator::Call {
                                     destination_local: FnLocal::new(
                                         destination.local.as_u32(),
-                                        fn_id.local_def_index.as_u32(),
+                                        fn_id,
                                     ),
                                     fn_span,
+                                    clone: is_clone(func),
                                 }
                             }),
                             _ => Some(MirTerminator::Other),
@@ -395,6 +648,93 @@ impl MirAnalyzer<'_> {
             .collect()
     }
 
+    /// collect closures constructed in the body with their captures
+    fn closures<'tcx>(
+        tcx: TyCtxt<'tcx>,
+        fn_id: u64,
+        source: &utils::LineIndex,
+        offset: u32,
+        basic_blocks: &BasicBlocks<'tcx>,
+        mir_place: impl Fn(Place<'tcx>) -> MirPlace,
+    ) -> Vec<MirClosure> {
+        // captures by reference are passed through a temporary holding the borrow
+        let mut ref_temps = HashMap::new();
+        for bb_data in basic_blocks.iter() {
+            for statement in &bb_data.statements {
+                if let StatementKind::Assign(v) = &statement.kind {
+                    if let (place, Rvalue::Ref(_, _, borrowed)) = &**v {
+                        if let Some(local) = place.as_local() {
+                            ref_temps.insert(local, *borrowed);
+                        }
+                    }
+                }
+            }
+        }
+
+        let mut closures = Vec::new();
+        for bb_data in basic_blocks.iter() {
+            for statement in &bb_data.statements {
+                let StatementKind::Assign(v) = &statement.kind else {
+                    continue;
+                };
+                let (place, Rvalue::Aggregate(aggregate, operands)) = &**v else {
+                    continue;
+                };
+                let AggregateKind::Closure(closure_id, args) = &**aggregate else {
+                    continue;
+                };
+                let Some(closure_id) = closure_id.as_local() else {
+                    continue;
+                };
+                let Some(range) = range_from_span(source, statement.source_info.span, offset)
+                else {
+                    continue;
+                };
+                let captures = tcx
+                    .closure_captures(closure_id)
+                    .iter()
+                    .zip(operands.iter())
+                    .filter_map(|(capture, operand)| {
+                        let (captured, kind) = match (capture.info.capture_kind, operand) {
+                            (UpvarCapture::ByRef(kind), Operand::Move(p) | Operand::Copy(p)) => (
+                                p.as_local().and_then(|v| ref_temps.get(&v)).copied()?,
+                                if matches!(kind, ty::BorrowKind::Immutable) {
+                                    MirCaptureKind::Ref
+                                } else {
+                                    MirCaptureKind::MutRef
+                                },
+                            ),
+                            (_, Operand::Move(p)) => (*p, MirCaptureKind::Move),
+                            (_, Operand::Copy(p)) => (*p, MirCaptureKind::Copy),
+                            (_, Operand::Constant(_)) => return None,
+                        };
+                        range_from_span(source, capture.get_path_span(tcx), offset).map(|range| {
+                            MirCapture {
+                                local: FnLocal::new(captured.local.as_u32(), fn_id),
+                                place: mir_place(captured),
+                                kind,
+                                range,
+                            }
+                        })
+                    })
+                    .collect();
+                let kind = match args.as_closure().kind() {
+                    ty::ClosureKind::Fn => MirClosureKind::Fn,
+                    ty::ClosureKind::FnMut => MirClosureKind::FnMut,
+                    ty::ClosureKind::FnOnce => MirClosureKind::FnOnce,
+                };
+                closures.push(MirClosure {
+                    local: FnLocal::new(place.local.as_u32(), fn_id),
+                    fn_id: stable_fn_id(tcx, closure_id),
+                    kind,
+                    range,
+                    captures,
+                });
+            }
+        }
+        closures
+    }
+
     fn erase_superset(mut ranges: Vec<Range>, erase_subset: bool) -> Vec<Range> {
         let mut len = ranges.len();
         let mut i = 0;
@@ -437,61 +777,203 @@ impl MirAnalyzer<'_> {
             .map(|(local, locations)| {
                 (
                     local,
-                    utils::eliminated_ranges(self.rich_locations_to_ranges(&locations)),
+                    RangeSet::from(self.rich_locations_to_ranges(&locations)).into_vec(),
                 )
             })
             .collect()
     }
-    /// returns (shared, mutable)
-    fn get_borrow_live(&self) -> (HashMap<Local, Vec<Range>>, HashMap<Local, Vec<Range>>) {
+    /// returns where borrows of each place of the locals are live
+    fn get_place_borrows(&self) -> HashMap<Local, Vec<MirPlaceBorrow>> {
         let output = &self.output_datafrog;
-        let mut shared_borrows = HashMap::new();
-        let mut mutable_borrows = HashMap::new();
+        let mut place_borrows: HashMap<_, (Vec<_>, Vec<_>)> = HashMap::new();
         for (location_idx, borrow_idc) in output.loan_live_at.iter() {
             let location = self.location_table.to_rich_location(*location_idx);
             for borrow_idx in borrow_idc {
                 let borrow_data = &self.borrow_set[*borrow_idx];
                 let local = borrow_data.borrowed_place().local;
+                let place = self.borrow_places[borrow_idx].clone();
+                let (place_shared, place_mutable) =
+                    place_borrows.entry((local, place)).or_default();
                 if borrow_data.kind().mutability().is_mut() {
-                    mutable_borrows
-                        .entry(local)
-                        .or_insert_with(Vec::new)
-                        .push(location);
+                    place_mutable.push(location);
                 } else {
-                    shared_borrows
-                        .entry(local)
-                        .or_insert_with(Vec::new)
-                        .push(location);
+                    place_shared.push(location);
                 }
             }
         }
-        (
-            shared_borrows
-                .into_iter()
-                .map(|(local, locations)| {
-                    (
-                        local,
-                        utils::eliminated_ranges(self.rich_locations_to_ranges(&locations)),
-                    )
-                })
-                .collect(),
-            mutable_borrows
-                .into_iter()
-                .map(|(local, locations)| {
-                    (
-                        local,
-                        utils::eliminated_ranges(self.rich_locations_to_ranges(&locations)),
-                    )
+        let mut places: HashMap<Local, Vec<MirPlaceBorrow>> = HashMap::new();
+        for ((local, place), (shared, mutable)) in place_borrows {
+            places.append(
+                &local,
+                MirPlaceBorrow {
+                    place,
+                    shared_borrow: RangeSet::from(self.rich_locations_to_ranges(&shared))
+                        .into_vec(),
+                    mutable_borrow: RangeSet::from(self.rich_locations_to_ranges(&mutable))
+                        .into_vec(),
+                },
+            );
+        }
+        for borrows in places.values_mut() {
+            borrows.sort_by(|a, b| a.place.path.cmp(&b.place.path));
+        }
+        places
+    }
+
+    /// describes regions by what they belong to, e.g. a variable or the signature
+    fn region_describer(&self) -> impl Fn(Region) -> MirRegion + '_ {
+        let fn_id = self.fn_id;
+        let regions_of = |local: Local| {
+            self.body.local_decls[local]
+                .ty
+                .walk()
+                .filter_map(|v| v.as_region())
+                .filter_map(|v| match v.kind() {
+                    ty::ReVar(vid) => Some(Region::from(vid)),
+                    _ => None,
                 })
-                .collect(),
-        )
+        };
+        let returned: BTreeSet<Region> = regions_of(RETURN_PLACE).collect();
+        let mut var_regions: HashMap<Region, (Local, String)> = HashMap::new();
+        for (local, (_, name)) in self.collect_user_vars() {
+            for region in regions_of(local) {
+                var_regions.entry(region).or_insert((local, name.clone()));
+            }
+        }
+        move |region: Region| {
+            let name = self.region_names.get(&region).cloned();
+            if self.input.universal_region.contains(&region) {
+                MirRegion::Universal { name }
+            } else if returned.contains(&region) {
+                MirRegion::Return { name }
+            } else if let Some((local, name)) = var_regions.get(&region) {
+                MirRegion::Variable {
+                    local: FnLocal::new(local.as_u32(), fn_id),
+                    name: name.clone(),
+                }
+            } else {
+                MirRegion::Anonymous
+            }
+        }
+    }
+
+    /// returns ranges where each local must live and why
+    fn get_must_live(&self) -> HashMap<Local, (Vec<Range>, Vec<MirBlame>)> {
+        let describe = self.region_describer();
+
+        // loans flow from a region into its supersets
+        let mut subsets: HashMap<Region, BTreeMap<Region, Vec<Point>>> = HashMap::new();
+        for (sub, sup, point) in self.input.subset_base.iter() {
+            subsets
+                .entry(*sub)
+                .or_default()
+                .entry(*sup)
+                .or_default()
+                .push(*point);
+        }
+        let issued: HashMap<Borrow, Region> = self
+            .input
+            .loan_issued_at
+            .iter()
+            .map(|(region, loan, _)| (*loan, *region))
+            .collect();
+        let mut paths: HashMap<Borrow, HashMap<Region, Option<Region>>> = HashMap::new();
+
+        let mut must_live = HashMap::new();
+        for (local, ranges) in self.live_range_from_region(&self.output_insensitive) {
+            let mut must_live_at = Vec::new();
+            let mut blamed: HashMap<(Borrow, Vec<Region>), Vec<Range>> = HashMap::new();
+            for (range, candidates) in ranges {
+                must_live_at.push(range);
+                // the region furthest from the borrow tells the root cause
+                if let Some(path) = candidates
+                    .into_iter()
+                    .filter_map(|(loan, region)| {
+                        let from = *issued.get(&loan)?;
+                        let previous = paths
+                            .entry(loan)
+                            .or_insert_with(|| Self::shortest_paths(from, &subsets));
+                        let mut path = vec![region];
+                        while let Some(Some(region)) = previous.get(path.last().unwrap()) {
+                            path.push(*region);
+                        }
+                        path.reverse();
+                        (path[0] == from).then_some((loan, path))
+                    })
+                    .max_by_key(|(_, path)| path.len())
+                {
+                    blamed.append(&path, range);
+                }
+            }
+
+            let mut blames = Vec::new();
+            for ((loan, path), ranges) in blamed {
+                let location = self.borrow_set[loan].reserve_location();
+                let Some(borrowed) =
+                    self.stmt_location_to_range(location.block, location.statement_index)
+                else {
+                    continue;
+                };
+                let mut chain: Vec<MirBlameStep> = Vec::new();
+                for (i, region) in path.iter().enumerate() {
+                    let described = describe(*region);
+                    // temporaries and repeated regions add nothing to the explanation
+                    if described == MirRegion::Anonymous
+                        || chain.last().is_some_and(|v| v.region == described)
+                    {
+                        continue;
+                    }
+                    // relations required at every point do not tell where the loan flows
+                    let range = i
+                        .checked_sub(1)
+                        .and_then(|prev| subsets.get(&path[prev])?.get(region))
+                        .filter(|points| points.len() == 1)
+                        .and_then(|points| self.point_to_range(points[0]));
+                    chain.push(MirBlameStep {
+                        region: described,
+                        range,
+                    });
+                }
+                for range in RangeSet::from(ranges).into_vec() {
+                    blames.push(MirBlame {
+                        range,
+                        place: self.borrow_places[&loan].clone(),
+                        borrowed,
+                        chain: chain.clone(),
+                    });
+                }
+            }
+            blames.sort_by_key(|v| (v.range.from(), v.range.until()));
+            must_live.insert(local, (must_live_at, blames));
+        }
+        must_live
     }
 
-    fn get_must_live(&self) -> HashMap<Local, Vec<Range>> {
-        self.live_range_from_region(&self.output_insensitive)
+    /// breadth-first search over subset relations; returns the previous region of each region
+    fn shortest_paths(
+        from: Region,
+        subsets: &HashMap<Region, BTreeMap<Region, Vec<Point>>>,
+    ) -> HashMap<Region, Option<Region>> {
+        let mut previous = HashMap::from([(from, None)]);
+        let mut queue = VecDeque::from([from]);
+        while let Some(region) = queue.pop_front() {
+            for sup in subsets.get(&region).into_iter().flat_map(|v| v.keys()) {
+                if !previous.contains_key(sup) {
+                    previous.insert(*sup, Some(region));
+                    queue.push_back(*sup);
+                }
+            }
+        }
+        previous
     }
 
-    fn live_range_from_region(&self, output: &PoloniusOutput) -> HashMap<Local, Vec<Range>> {
+    /// returns ranges where each local must live,
+    /// with the loans of the local and the regions keeping them alive there
+    #[allow(clippy::type_complexity)]
+    fn live_range_from_region(
+        &self,
+        output: &PoloniusOutput,
+    ) -> HashMap<Local, Vec<(Range, Vec<(Borrow, Region)>)>> {
         let mut region_locations = HashMap::new();
         for (location_idx, region_idc) in output.origin_live_on_entry.iter() {
             let location = self.location_table.to_rich_location(*location_idx);
@@ -503,33 +985,248 @@ impl MirAnalyzer<'_> {
             }
         }
 
-        // compute regions where the local must be live
+        // compute regions where the local must be live, with the loans they contain
         let mut local_must_regions: HashMap<Local, BTreeSet<Region>> = HashMap::new();
+        let mut region_loans: HashMap<(Local, Region), Vec<Borrow>> = HashMap::new();
         for (region_idx, borrow_idc) in output.origin_contains_loan_anywhere.iter() {
             for borrow_idx in borrow_idc {
                 if let Some(local) = self.borrow_locals.get(borrow_idx) {
                     local_must_regions.append(local, *region_idx);
+                    region_loans.append(&(*local, *region_idx), *borrow_idx);
                 }
             }
         }
+        let region_ranges: HashMap<Region, Vec<Range>> = region_locations
+            .iter()
+            .map(|(region, locations)| (*region, self.rich_locations_to_ranges(locations)))
+            .collect();
 
         HashMap::from_iter(local_must_regions.iter().map(|(local, regions)| {
-            (
-                *local,
-                Self::erase_superset(
-                    self.rich_locations_to_ranges(
-                        &regions
-                            .iter()
-                            .filter_map(|v| region_locations.get(v).cloned())
-                            .flatten()
-                            .collect::<Vec<_>>(),
-                    ),
-                    false,
+            let ranges = Self::erase_superset(
+                self.rich_locations_to_ranges(
+                    &regions
+                        .iter()
+                        .filter_map(|v| region_locations.get(v).cloned())
+                        .flatten()
+                        .collect::<Vec<_>>(),
                 ),
-            )
+                false,
+            );
+            let ranges = ranges
+                .into_iter()
+                .map(|range| {
+                    let candidates = regions
+                        .iter()
+                        .filter(|region| {
+                            region_ranges.get(region).is_some_and(|v| {
+                                v.iter()
+                                    .any(|v| v.from() < range.until() && range.from() < v.until())
+                            })
+                        })
+                        .flat_map(|region| {
+                            region_loans[&(*local, *region)]
+                                .iter()
+                                .map(|loan| (*loan, *region))
+                        })
+                        .collect();
+                    (range, candidates)
+                })
+                .collect();
+            (*local, ranges)
         }))
     }
 
+    /// collect borrow check errors computed by Polonius
+    fn collect_errors(&self) -> Vec<MirBorrowError> {
+        let output = &self.output_datafrog;
+        let fn_id = self.fn_id;
+        let mut errors = Vec::new();
+
+        // a loan may be invalidated at several points of one expression; keep the narrowest
+        let mut loan_errors: HashMap<Borrow, Range> = HashMap::new();
+        for (point, loans) in output.errors.iter() {
+            let Some(range) = self.point_to_range(*point) else {
+                continue;
+            };
+            for loan in loans {
+                let narrowest = loan_errors.entry(*loan).or_insert(range);
+                if range.size() < narrowest.size() {
+                    *narrowest = range;
+                }
+            }
+        }
+        let mut loan_errors: Vec<_> = loan_errors.into_iter().collect();
+        loan_errors.sort_by_key(|(_, range)| (range.from(), range.until()));
+        for (loan, invalidated) in loan_errors {
+            let borrow_data = &self.borrow_set[loan];
+            let location = borrow_data.reserve_location();
+            if let Some(borrowed) =
+                self.stmt_location_to_range(location.block, location.statement_index)
+            {
+                errors.push(MirBorrowError::Loan {
+                    local: FnLocal::new(borrow_data.borrowed_place().local.as_u32(), fn_id),
+                    place: self.borrow_places[&loan].clone(),
+                    mutable: borrow_data.kind().mutability().is_mut(),
+                    borrowed,
+                    invalidated,
+                });
+            }
+        }
+
+        // the same unsatisfied relation is reported at every point; report it at the
+        // narrowest one, and the others as related points
+        let mut subset_errors: HashMap<(Region, Region), Vec<Range>> = HashMap::new();
+        for (point, subsets) in output.subset_errors.iter() {
+            let Some(range) = self.point_to_range(*point) else {
+                continue;
+            };
+            for subset in subsets {
+                subset_errors.append(subset, range);
+            }
+        }
+        let mut subset_errors: Vec<_> = subset_errors
+            .into_iter()
+            .filter_map(|(subset, mut points)| {
+                points.sort_by_key(|range| (range.from(), range.until()));
+                points.dedup();
+                let range = *points.iter().min_by_key(|range| range.size())?;
+                points.retain(|v| *v != range);
+                Some((range, subset, points))
+            })
+            .collect();
+        subset_errors.sort_by_key(|(range, _, _)| (range.from(), range.until()));
+        let describe = self.region_describer();
+        for (range, (longer, shorter), points) in subset_errors {
+            errors.push(MirBorrowError::Subset {
+                range,
+                longer: describe(longer),
+                shorter: describe(shorter),
+                points,
+            });
+        }
+
+        // move paths are tracked per local, so resolve each path to its root variable
+        let path_parents: HashMap<MovePath, MovePath> =
+            self.input.child_path.iter().copied().collect();
+        let path_locals: HashMap<MovePath, Local> =
+            self.input.path_is_var.iter().copied().collect();
+        let root_local = |mut path: MovePath| loop {
+            if let Some(local) = path_locals.get(&path) {
+                return Some(*local);
+            }
+            path = *path_parents.get(&path)?;
+        };
+        let user_vars = self.collect_user_vars();
+        for (point, paths) in output.move_errors.iter() {
+            let Some(accessed) = self.point_to_range(*point) else {
+                continue;
+            };
+            for local in paths.iter().filter_map(|path| root_local(*path)) {
+                let moved = self
+                    .input
+                    .path_moved_at_base
+                    .iter()
+                    .filter(|(path, _)| root_local(*path) == Some(local))
+                    .filter(|(_, point)| !self.is_storage_marker(*point))
+                    .filter_map(|(_, point)| self.point_to_range(*point))
+                    .collect::<RangeSet>()
+                    .into_vec();
+                let name = user_vars
+                    .get(&local)
+                    .map(|(_, name)| name.clone())
+                    .unwrap_or_else(|| format!("{local:?}"));
+                errors.push(MirBorrowError::Move {
+                    local: FnLocal::new(local.as_u32(), fn_id),
+                    place: MirPlace::new(&name, Vec::new()),
+                    moved,
+                    accessed,
+                });
+            }
+        }
+        errors
+    }
+
+    /// collect loans with the user variables holding each of them
+    fn collect_loans(&self) -> Vec<MirLoan> {
+        let output = &self.output_datafrog;
+        let fn_id = self.fn_id;
+        let user_vars = self.collect_user_vars();
+
+        // regions appearing in the type of each user variable
+        let mut var_regions: HashMap<Local, BTreeSet<Region>> = HashMap::new();
+        for (local, region) in self
+            .input
+            .use_of_var_derefs_origin
+            .iter()
+            .chain(self.input.drop_of_var_derefs_origin.iter())
+        {
+            if user_vars.contains_key(local) {
+                var_regions.append(local, *region);
+            }
+        }
+
+        // a variable holds a loan where it is live and one of its regions contains the loan
+        let mut holder_locations: HashMap<(Borrow, Local), Vec<RichLocation>> = HashMap::new();
+        for (point, loans) in output.loan_live_at.iter() {
+            let Some(region_loans) = output.origin_contains_loan_at.get(point) else {
+                continue;
+            };
+            let location = self.location_table.to_rich_location(*point);
+            for (local, regions) in &var_regions {
+                let live = output
+                    .var_live_on_entry
+                    .get(point)
+                    .into_iter()
+                    .chain(output.var_drop_live_on_entry.get(point))
+                    .any(|v| v.contains(local));
+                if !live {
+                    continue;
+                }
+                for loan in loans {
+                    if regions
+                        .iter()
+                        .any(|v| region_loans.get(v).is_some_and(|l| l.contains(loan)))
+                    {
+                        holder_locations.append(&(*loan, *local), location);
+                    }
+                }
+            }
+        }
+
+        let mut loans: Vec<_> = self.borrow_locals.iter().collect();
+        loans.sort_by_key(|(loan, _)| **loan);
+        loans
+            .into_iter()
+            .filter_map(|(loan, local)| {
+                let borrow_data = &self.borrow_set[*loan];
+                let location = borrow_data.reserve_location();
+                let range =
+                    self.stmt_location_to_range(location.block, location.statement_index)?;
+                let mut holders: Vec<_> = user_vars
+                    .keys()
+                    .filter(|v| *v != local)
+                    .filter_map(|v| {
+                        let locations = holder_locations.get(&(*loan, *v))?;
+                        let live =
+                            RangeSet::from(self.rich_locations_to_ranges(locations)).into_vec();
+                        (!live.is_empty()).then(|| MirLoanHolder {
+                            local: FnLocal::new(v.as_u32(), fn_id),
+                            live,
+                        })
+                    })
+                    .collect();
+                holders.sort_by_key(|v| v.local.id);
+                Some(MirLoan {
+                    local: FnLocal::new(local.as_u32(), fn_id),
+                    place: self.borrow_places[loan].clone(),
+                    mutable: borrow_data.kind().mutability().is_mut(),
+                    range,
+                    holders,
+                })
+            })
+            .collect()
+    }
+
     fn is_drop(&self, local: Local) -> bool {
         for (drop_local, _) in self.input.var_dropped_at.iter() {
             if *drop_local == local {
@@ -542,14 +1239,25 @@ impl MirAnalyzer<'_> {
     /// analyze MIR to get JSON-serializable, TypeScript friendly representation
     pub fn analyze(self) -> (String, Function) {
         let decls = self.collect_decls();
+        let loans = self.collect_loans();
+        let errors = self.collect_errors();
         let basic_blocks = self.basic_blocks;
+        let closures = self.closures;
 
         (
             self.filename,
             Function {
-                fn_id: self.fn_id.local_def_index.as_u32(),
+                fn_id: self.fn_id,
+                key: self.key,
+                path: self.path,
+                signature: self.signature,
+                span: self.span,
                 basic_blocks,
                 decls,
+                closures,
+                loans,
+                errors,
+                stale: false,
             },
         )
     }
@@ -0,0 +1,141 @@
+//! Per-crate analysis results, keyed by a fingerprint of sources and flags
+
+use super::overlay;
+use rustowl::{
+    cache::{Invocation, StableHasher},
+    models::*,
+};
+use serde::{Deserialize, Serialize};
+use std::env;
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+/// changed when the analysis or the format of results changes without a new release,
+/// including the compiler options set in `configure`
+const CACHE_VERSION: &str = "3";
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+struct CacheEntry {
+    fingerprint: String,
+    sources: Vec<PathBuf>,
+    workspace: Workspace,
+}
+
+fn arg_value<'a>(args: &'a [String], name: &str) -> Option<&'a str> {
+    args.iter()
+        .position(|v| v == name)
+        .and_then(|i| args.get(i + 1))
+        .map(|v| v.as_str())
+}
+
+/// one file per compilation unit, which cargo distinguishes by `extra-filename`
+fn unit_file(args: &[String]) -> Option<String> {
+    let crate_name = arg_value(args, "--crate-name")?;
+    let extra = args
+        .iter()
+        .find_map(|v| v.strip_prefix("extra-filename="))
+        .unwrap_or("");
+    Some(format!("{crate_name}{extra}.json"))
+}
+
+fn cache_path(args: &[String]) -> Option<PathBuf> {
+    let target = env::var("CARGO_TARGET_DIR").ok()?;
+    Some(Path::new(&target).join("crates").join(unit_file(args)?))
+}
+
+/// hash of everything the results of a compilation unit depend on
+///
+/// Any change of the following invalidates the cache:
+/// - [`CACHE_VERSION`] and the rustowlc version
+/// - compiler arguments, which cargo derives from features, `--cfg`, `-C`/`-Z` options,
+///   `RUSTFLAGS`, the edition and the paths of dependencies
+/// - content of the crate sources, read through unsaved buffers
+/// - modification time and size of dependency artifacts passed with `--extern`,
+///   which cargo rewrites when a dependency is checked again
+fn fingerprint(args: &[String], sources: &[PathBuf]) -> String {
+    let mut hasher = StableHasher::default();
+    hasher.write(CACHE_VERSION.as_bytes());
+    hasher.write(env!("CARGO_PKG_VERSION").as_bytes());
+    for arg in args {
+        hasher.write(arg.as_bytes());
+    }
+    for path in sources {
+        hasher.write(path.as_os_str().as_encoded_bytes());
+        match overlay::read(path) {
+            Ok(content) => hasher.write(&content),
+            Err(_) => hasher.write(b"missing"),
+        }
+    }
+    let externs = args
+        .windows(2)
+        .filter(|v| v[0] == "--extern")
+        .filter_map(|v| v[1].split_once('=').map(|(_, path)| Path::new(path)));
+    for path in externs {
+        hasher.write(path.as_os_str().as_encoded_bytes());
+        let metadata = std::fs::metadata(path).ok();
+        let modified = metadata
+            .as_ref()
+            .and_then(|v| v.modified().ok())
+            .and_then(|v| v.duration_since(UNIX_EPOCH).ok())
+            .map(|v| v.as_nanos())
+            .unwrap_or(0);
+        hasher.write(&modified.to_le_bytes());
+        hasher.write(&metadata.map(|v| v.len()).unwrap_or(0).to_le_bytes());
+    }
+    hasher.finish()
+}
+
+/// returns saved results and sources if the crate has not changed since they were saved
+pub fn load(args: &[String]) -> Option<(Workspace, Vec<PathBuf>)> {
+    let content = std::fs::read(cache_path(args)?).ok()?;
+    let entry: CacheEntry = serde_json::from_slice(&content).ok()?;
+    (entry.fingerprint == fingerprint(args, &entry.sources))
+        .then_some((entry.workspace, entry.sources))
+}
+
+pub fn save(args: &[String], sources: Vec<PathBuf>, workspace: Workspace) {
+    let Some(path) = cache_path(args) else {
+        return;
+    };
+    let entry = CacheEntry {
+        fingerprint: fingerprint(args, &sources),
+        sources,
+        workspace,
+    };
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent).ok();
+    }
+    if let Ok(content) = serde_json::to_vec(&entry) {
+        std::fs::write(path, content).ok();
+    }
+}
+
+/// record how the crate is compiled, for the LSP backend to run rustowlc on it directly
+pub fn record_invocation(args: &[String], sources: &[PathBuf]) {
+    let (Ok(target), Some(file), Ok(cwd)) = (
+        env::var("CARGO_TARGET_DIR"),
+        unit_file(args),
+        env::current_dir(),
+    ) else {
+        return;
+    };
+    let invocation = Invocation {
+        args: args.iter().skip(1).cloned().collect(),
+        env: env::vars()
+            .filter(|(key, _)| key.starts_with("CARGO") || key == "OUT_DIR")
+            .collect(),
+        sources: sources
+            .iter()
+            .map(|v| {
+                let path = cwd.join(v);
+                path.canonicalize().unwrap_or(path)
+            })
+            .collect(),
+        cwd,
+    };
+    let dir = Invocation::dir(target);
+    std::fs::create_dir_all(&dir).ok();
+    if let Ok(content) = serde_json::to_vec(&invocation) {
+        std::fs::write(dir.join(file), content).ok();
+    }
+}
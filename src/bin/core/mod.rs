@@ -1,6 +1,8 @@
 #![allow(clippy::await_holding_lock)]
 
 mod analyze;
+mod cache;
+mod overlay;
 
 use analyze::MirAnalyzer;
 use rustc_hir::def_id::{LOCAL_CRATE, LocalDefId};
@@ -10,10 +12,12 @@ use rustc_middle::{
     util::Providers,
 };
 use rustc_session::config;
-use rustowl::models::*;
+use rustc_span::FileName;
+use rustowl::{models::*, utils::LineIndex};
 use std::collections::HashMap;
 use std::env;
-use std::sync::{LazyLock, Mutex, atomic::AtomicBool};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, LazyLock, Mutex, atomic::AtomicBool};
 use tokio::{
     runtime::{Builder, Handle, Runtime},
     task::JoinSet,
@@ -37,10 +41,42 @@ static RUNTIME: LazyLock<Mutex<Runtime>> = LazyLock::new(|| {
 });
 static HANDLE: LazyLock<Handle> = LazyLock::new(|| RUNTIME.lock().unwrap().handle().clone());
 static ANALYZED: LazyLock<Mutex<Vec<LocalDefId>>> = LazyLock::new(|| Mutex::new(Vec::new()));
+static SOURCES: LazyLock<Mutex<Vec<PathBuf>>> = LazyLock::new(|| Mutex::new(Vec::new()));
+/// line indices of source files, shared by analyzers of functions in the same file
+static LINE_INDICES: LazyLock<Mutex<HashMap<PathBuf, Arc<LineIndex>>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+static RESULTS: LazyLock<Mutex<Workspace>> =
+    LazyLock::new(|| Mutex::new(Workspace(HashMap::new())));
+
+/// line index of the source file, built once per file
+fn line_index(path: &Path) -> Arc<LineIndex> {
+    LINE_INDICES
+        .lock()
+        .unwrap()
+        .entry(path.to_path_buf())
+        .or_insert_with(|| Arc::new(LineIndex::new(&overlay::read_to_string(path).unwrap())))
+        .clone()
+}
 
 fn override_queries(_session: &rustc_session::Session, local: &mut Providers) {
     local.mir_borrowck = mir_borrowck;
 }
+fn override_queries_cached(_session: &rustc_session::Session, local: &mut Providers) {
+    local.mir_borrowck = mir_borrowck_cached;
+}
+fn borrowck_result(tcx: TyCtxt<'_>) -> ProvidedValue<'_> {
+    let result = BorrowCheckResult {
+        concrete_opaque_types: indexmap::IndexMap::default(),
+        closure_requirements: None,
+        used_mut_upvars: smallvec::SmallVec::new(),
+        tainted_by_errors: None,
+    };
+
+    tcx.arena.alloc(result)
+}
+fn mir_borrowck_cached(tcx: TyCtxt<'_>, _def_id: LocalDefId) -> ProvidedValue<'_> {
+    borrowck_result(tcx)
+}
 fn mir_borrowck(tcx: TyCtxt<'_>, def_id: LocalDefId) -> ProvidedValue<'_> {
     log::info!("start borrowck of {def_id:?}");
 
@@ -68,41 +104,57 @@ fn mir_borrowck(tcx: TyCtxt<'_>, def_id: LocalDefId) -> ProvidedValue<'_> {
     };
     let crate_name = tcx.crate_name(LOCAL_CRATE).to_string();
     if current == mir_len {
+        *SOURCES.lock().unwrap() = tcx
+            .sess
+            .source_map()
+            .files()
+            .iter()
+            .filter(|v| !v.is_imported())
+            .filter_map(|v| match &v.name {
+                FileName::Real(name) => name.local_path().map(|w| w.to_path_buf()),
+                _ => None,
+            })
+            .collect();
         RUNTIME.lock().unwrap().block_on(async move {
             while let Some(task) = { TASKS.lock().unwrap().join_next().await } {
                 let (filename, analyzed) = task.unwrap().analyze();
                 log::info!("analyzed one item of {}", filename);
-                let krate = Crate(HashMap::from([(
-                    filename,
-                    File {
-                        items: vec![analyzed],
-                    },
-                )]));
+                let krate = Crate(HashMap::from([(filename, File::new(vec![analyzed]))]));
                 let ws = Workspace(HashMap::from([(crate_name.clone(), krate)]));
                 println!("{}", serde_json::to_string(&ws).unwrap());
+                RESULTS.lock().unwrap().merge(ws);
             }
         })
     }
 
-    let result = BorrowCheckResult {
-        concrete_opaque_types: indexmap::IndexMap::default(),
-        closure_requirements: None,
-        used_mut_upvars: smallvec::SmallVec::new(),
-        tainted_by_errors: None,
-    };
+    borrowck_result(tcx)
+}
 
-    tcx.arena.alloc(result)
+/// options shared by analyzed and cached runs;
+/// tracked options are hashed into the emitted metadata, so both runs must agree on them
+fn configure(config: &mut interface::Config) {
+    config.using_internal_features = &ATOMIC_TRUE;
+    config.opts.unstable_opts.mir_opt_level = Some(0);
+    config.opts.unstable_opts.polonius = config::Polonius::Next;
+    config.opts.incremental = None;
+    config.file_loader = Some(Box::new(overlay::OverlayFileLoader));
+    config.make_codegen_backend = None;
 }
 
 pub struct AnalyzerCallback;
 impl rustc_driver::Callbacks for AnalyzerCallback {
     fn config(&mut self, config: &mut interface::Config) {
-        config.using_internal_features = &ATOMIC_TRUE;
-        config.opts.unstable_opts.mir_opt_level = Some(0);
-        config.opts.unstable_opts.polonius = config::Polonius::Next;
-        config.opts.incremental = None;
+        configure(config);
         config.override_queries = Some(override_queries);
-        config.make_codegen_backend = None;
+    }
+}
+
+/// checks the crate without analysis, as its results are restored from cache
+pub struct CachedCallback;
+impl rustc_driver::Callbacks for CachedCallback {
+    fn config(&mut self, config: &mut interface::Config) {
+        configure(config);
+        config.override_queries = Some(override_queries_cached);
     }
 }
 
@@ -124,7 +176,25 @@ pub fn run_compiler() -> i32 {
         }
     }
 
-    rustc_driver::catch_with_exit_code(|| {
+    if let Some((ws, sources)) = cache::load(&args) {
+        log::info!("analysis results restored from cache");
+        cache::record_invocation(&args, &sources);
+        println!("{}", serde_json::to_string(&ws).unwrap());
+        return rustc_driver::catch_with_exit_code(|| {
+            rustc_driver::run_compiler(&args, &mut CachedCallback)
+        });
+    }
+
+    let code = rustc_driver::catch_with_exit_code(|| {
         rustc_driver::run_compiler(&args, &mut AnalyzerCallback);
-    })
+    });
+    if code == 0 {
+        let sources = std::mem::take(&mut *SOURCES.lock().unwrap());
+        let results = std::mem::replace(&mut *RESULTS.lock().unwrap(), Workspace(HashMap::new()));
+        if !sources.is_empty() {
+            cache::record_invocation(&args, &sources);
+            cache::save(&args, sources, results);
+        }
+    }
+    code
 }
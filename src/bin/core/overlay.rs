@@ -0,0 +1,47 @@
+//! Unsaved editor buffers, read in place of the files on disk
+//!
+//! The LSP backend writes each unsaved buffer to a temporary file and passes
+//! the mapping from the original path in `RUSTOWL_OVERLAY` as JSON.
+
+use rustc_span::source_map::{FileLoader, RealFileLoader};
+use std::collections::HashMap;
+use std::env;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, LazyLock};
+
+static OVERLAY: LazyLock<HashMap<PathBuf, PathBuf>> = LazyLock::new(|| {
+    env::var("RUSTOWL_OVERLAY")
+        .ok()
+        .and_then(|v| serde_json::from_str(&v).ok())
+        .unwrap_or_default()
+});
+
+fn mapped(path: &Path) -> &Path {
+    path.canonicalize()
+        .ok()
+        .and_then(|v| OVERLAY.get(&v))
+        .map(|v| v.as_path())
+        .unwrap_or(path)
+}
+
+pub fn read(path: impl AsRef<Path>) -> io::Result<Vec<u8>> {
+    std::fs::read(mapped(path.as_ref()))
+}
+
+pub fn read_to_string(path: impl AsRef<Path>) -> io::Result<String> {
+    std::fs::read_to_string(mapped(path.as_ref()))
+}
+
+pub struct OverlayFileLoader;
+impl FileLoader for OverlayFileLoader {
+    fn file_exists(&self, path: &Path) -> bool {
+        RealFileLoader.file_exists(path)
+    }
+    fn read_file(&self, path: &Path) -> io::Result<String> {
+        RealFileLoader.read_file(mapped(path))
+    }
+    fn read_binary_file(&self, path: &Path) -> io::Result<Arc<[u8]>> {
+        RealFileLoader.read_binary_file(mapped(path))
+    }
+}
@@ -87,7 +87,12 @@ async fn main() {
         let stdout = tokio::io::stdout();
 
         let (service, socket) = LspService::build(Backend::new)
+            .custom_method("rustowl/borrowers", Backend::borrowers)
             .custom_method("rustowl/cursor", Backend::cursor)
+            .custom_method("rustowl/fileDecorations", Backend::file_decorations)
+            .custom_method("rustowl/functionOverview", Backend::function_overview)
+            .custom_method("rustowl/pin", Backend::pin)
+            .custom_method("rustowl/unpin", Backend::unpin)
             .finish();
         Server::new(stdin, stdout, socket).serve(service).await;
     }
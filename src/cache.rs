@@ -0,0 +1,175 @@
+//! # RustOwl cache
+//!
+//! Persisted analysis results, validated against the current source files
+
+use crate::lsp::document::Documents;
+use crate::models::*;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+/// FNV-1a hash, which unlike `DefaultHasher` is the same across Rust releases,
+/// so that it can be persisted
+#[derive(Clone, Copy, Debug)]
+pub struct StableHasher(u64);
+impl Default for StableHasher {
+    fn default() -> Self {
+        Self(0xcbf29ce484222325)
+    }
+}
+impl StableHasher {
+    /// hashes the bytes prefixed with their length, so that consecutive writes
+    /// cannot be confused with each other
+    pub fn write(&mut self, bytes: &[u8]) {
+        for byte in (bytes.len() as u64).to_le_bytes().iter().chain(bytes) {
+            self.0 ^= u64::from(*byte);
+            self.0 = self.0.wrapping_mul(0x100000001b3);
+        }
+    }
+    pub fn finish(&self) -> String {
+        format!("{:016x}", self.0)
+    }
+}
+
+/// hash of file content to detect changes
+pub fn content_hash(content: &[u8]) -> String {
+    let mut hasher = StableHasher::default();
+    hasher.write(content);
+    hasher.finish()
+}
+
+async fn modified(path: impl AsRef<Path>) -> Option<SystemTime> {
+    tokio::fs::metadata(path)
+        .await
+        .and_then(|v| v.modified())
+        .ok()
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct CachedFile {
+    pub hash: String,
+    pub modified: Option<SystemTime>,
+    pub file: File,
+}
+
+#[derive(Serialize, Deserialize, Clone, Default, Debug)]
+#[serde(transparent)]
+pub struct AnalysisCache(pub HashMap<String, CachedFile>);
+impl AnalysisCache {
+    /// record analyzed files under `root` with their current content hash
+    ///
+    /// Unsaved documents are recorded with the hash of their text,
+    /// so they are stale on the next startup unless saved as they are.
+    pub async fn from_crate(krate: &Crate, root: impl AsRef<Path>, documents: &Documents) -> Self {
+        let mut cache = HashMap::new();
+        for (filename, file) in krate.0.iter() {
+            if !Path::new(filename).starts_with(&root) {
+                continue;
+            }
+            let Ok(content) = tokio::fs::read(filename).await else {
+                continue;
+            };
+            let (hash, modified) = match documents.get(Path::new(filename)) {
+                Some(document) if document.text.as_bytes() != content => {
+                    (content_hash(document.text.as_bytes()), None)
+                }
+                _ => (content_hash(&content), modified(filename).await),
+            };
+            cache.insert(
+                filename.clone(),
+                CachedFile {
+                    hash,
+                    modified,
+                    file: file.clone(),
+                },
+            );
+        }
+        Self(cache)
+    }
+
+    pub async fn load(path: impl AsRef<Path>) -> Option<Self> {
+        let content = tokio::fs::read(path).await.ok()?;
+        serde_json::from_slice(&content).ok()
+    }
+
+    pub async fn save(&self, path: impl AsRef<Path>) {
+        if let Ok(content) = serde_json::to_vec(self) {
+            tokio::fs::write(path, content).await.ok();
+        }
+    }
+
+    /// returns results of unchanged files and names of changed files
+    pub async fn validate(self) -> (Crate, Vec<String>) {
+        let mut fresh = HashMap::new();
+        let mut stale = Vec::new();
+        for (filename, cached) in self.0 {
+            let modified = modified(&filename).await;
+            // content is hashed only if the modification time differs
+            let unchanged = (modified.is_some() && modified == cached.modified)
+                || tokio::fs::read(&filename)
+                    .await
+                    .is_ok_and(|content| content_hash(&content) == cached.hash);
+            if unchanged {
+                fresh.insert(filename, cached.file);
+            } else {
+                stale.push(filename);
+            }
+        }
+        (Crate(fresh), stale)
+    }
+}
+
+/// how cargo ran rustowlc on a crate, recorded so that the crate can be analyzed
+/// again with unsaved buffers without checking the whole workspace
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Invocation {
+    /// compiler arguments, without the program
+    pub args: Vec<String>,
+    pub cwd: PathBuf,
+    /// variables cargo sets for the compiler, e.g. `CARGO_PKG_NAME` and `OUT_DIR`
+    pub env: Vec<(String, String)>,
+    /// canonical paths of the crate sources
+    pub sources: Vec<PathBuf>,
+}
+impl Invocation {
+    /// directory of invocations under the target directory of rustowlc
+    pub fn dir(target: impl AsRef<Path>) -> PathBuf {
+        target.as_ref().join("invocations")
+    }
+
+    /// invocations of crates compiling the file
+    pub async fn of_file(target: impl AsRef<Path>, path: impl AsRef<Path>) -> Vec<Self> {
+        let mut invocations = Vec::new();
+        let Ok(mut entries) = tokio::fs::read_dir(Self::dir(target)).await else {
+            return invocations;
+        };
+        while let Ok(Some(entry)) = entries.next_entry().await {
+            let Ok(content) = tokio::fs::read(entry.path()).await else {
+                continue;
+            };
+            if let Ok(invocation) = serde_json::from_slice::<Self>(&content) {
+                if invocation.sources.iter().any(|v| v == path.as_ref()) {
+                    invocations.push(invocation);
+                }
+            }
+        }
+        invocations
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn content_hash_is_stable() {
+        // persisted hashes must not change with the Rust release or the platform
+        assert_eq!(content_hash(b""), "a8c7f832281a39c5");
+        assert_eq!(content_hash(b"fn main() {}"), content_hash(b"fn main() {}"));
+        assert_ne!(
+            content_hash(b"fn main() {}"),
+            content_hash(b"fn main() { }")
+        );
+    }
+}
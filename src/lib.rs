@@ -2,10 +2,13 @@
 //!
 //! Libraries that used in RustOwl
 
+pub mod cache;
 pub mod cli;
 pub mod lsp;
 pub mod models;
 pub mod shells;
+#[cfg(test)]
+mod testing;
 pub mod toolchain;
 pub mod utils;
 
@@ -1,3 +1,14 @@
 pub mod backend;
+pub mod borrower;
+pub mod code_action;
+pub mod code_lens;
 pub mod decoration;
+pub mod diagnostic;
+pub mod document;
+pub mod hover;
+pub mod inlay;
+pub mod notification;
+pub mod overview;
+pub mod pin;
 pub mod progress;
+pub mod semantic;
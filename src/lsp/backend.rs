@@ -1,13 +1,13 @@
-use crate::{lsp::*, models::*, toolchain, utils};
-use std::collections::HashMap;
+use crate::{cache, lsp::*, models::*, toolchain, utils};
+use std::collections::{BTreeSet, HashMap};
 use std::env;
 use std::path::{Path, PathBuf};
 use std::sync::Arc;
 use tokio::{
-    io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+    io::{AsyncBufReadExt, BufReader},
     process,
     sync::RwLock,
-    task::JoinSet,
+    task::{JoinHandle, JoinSet},
 };
 use tower_lsp::jsonrpc;
 use tower_lsp::lsp_types;
@@ -24,31 +24,127 @@ enum CargoCheckMessage {
 
 type Subprocess = Option<u32>;
 
-/// RustOwl LSP server backend
+/// unsaved documents written for an analysis, removed once it finishes or is aborted
 #[derive(Debug)]
+struct OverlayDir(PathBuf);
+impl Drop for OverlayDir {
+    fn drop(&mut self) {
+        std::fs::remove_dir_all(&self.0).ok();
+    }
+}
+/// document version an analysis waits for, and the waiting task
+type PendingAnalysis = (i32, JoinHandle<()>);
+
+/// RustOwl LSP server backend
+#[derive(Clone, Debug)]
 pub struct Backend {
-    #[allow(unused)]
     client: Client,
     workspaces: Arc<RwLock<Vec<PathBuf>>>,
     roots: Arc<RwLock<HashMap<PathBuf, PathBuf>>>,
     status: Arc<RwLock<progress::AnalysisStatus>>,
     analyzed: Arc<RwLock<Option<Crate>>>,
+    documents: Arc<RwLock<document::Documents>>,
     processes: Arc<RwLock<JoinSet<()>>>,
     subprocesses: Arc<RwLock<Vec<Subprocess>>>,
     work_done_progress: Arc<RwLock<bool>>,
+    position_encoding: Arc<RwLock<utils::PositionEncoding>>,
+    pinned: Arc<RwLock<HashMap<PathBuf, Vec<FnLocal>>>>,
+    /// analyses waiting for typing to pause
+    pending: Arc<RwLock<HashMap<PathBuf, PendingAnalysis>>>,
+    /// files analyzed from unsaved buffers in the last analysis
+    overlaid: Arc<RwLock<Vec<PathBuf>>>,
+    /// results loaded from cache may depend on files changed since then
+    outdated: Arc<RwLock<bool>>,
+    _shutdown: Arc<ShutdownGuard>,
+}
+
+/// stops running analyses once the last clone of the backend is dropped;
+/// clones held by spawned tasks must not stop the analyses they start
+#[derive(Debug)]
+struct ShutdownGuard {
+    processes: Arc<RwLock<JoinSet<()>>>,
+    subprocesses: Arc<RwLock<Vec<Subprocess>>>,
+}
+impl Drop for ShutdownGuard {
+    fn drop(&mut self) {
+        tokio::task::block_in_place(|| {
+            tokio::runtime::Handle::current().block_on(async {
+                self.processes.write().await.shutdown().await;
+                abort_subprocesses(&self.subprocesses).await;
+            });
+        });
+    }
+}
+
+async fn abort_subprocesses(subprocesses: &RwLock<Vec<Subprocess>>) {
+    #[cfg(unix)]
+    while let Some(pid) = subprocesses.write().await.pop() {
+        if let Some(pid) = pid {
+            unsafe {
+                libc::killpg(pid.try_into().unwrap(), libc::SIGTERM);
+            }
+        }
+    }
+}
+
+/// merges results printed by rustowlc, one line per function, and notifies
+/// the files they belong to once per batch rather than once per function
+struct Results {
+    analyzed: Arc<RwLock<Option<Crate>>>,
+    client: Client,
+    updated: BTreeSet<String>,
+}
+impl Results {
+    fn new(backend: &Backend) -> Self {
+        Self {
+            analyzed: backend.analyzed.clone(),
+            client: backend.client.clone(),
+            updated: BTreeSet::new(),
+        }
+    }
+    /// merge the line if it is a result
+    async fn merge(&mut self, line: &str) {
+        let Ok(ws) = serde_json::from_str::<Workspace>(line) else {
+            return;
+        };
+        let write = &mut *self.analyzed.write().await;
+        for krate in ws.0.into_values() {
+            self.updated.extend(krate.0.keys().cloned());
+            write
+                .get_or_insert_with(|| Crate(HashMap::new()))
+                .merge(krate);
+        }
+    }
+    /// notify the files updated since the last call
+    async fn notify(&mut self) {
+        let updated = std::mem::take(&mut self.updated);
+        notification::analysis_updated(&self.client, updated).await;
+    }
 }
 
 impl Backend {
     pub fn new(client: Client) -> Self {
+        let processes = Arc::new(RwLock::new(JoinSet::new()));
+        let subprocesses = Arc::new(RwLock::new(vec![]));
         Self {
             client,
             workspaces: Arc::new(RwLock::new(Vec::new())),
             roots: Arc::new(RwLock::new(HashMap::new())),
             analyzed: Arc::new(RwLock::new(None)),
+            documents: Arc::new(RwLock::new(HashMap::new())),
             status: Arc::new(RwLock::new(progress::AnalysisStatus::Finished)),
-            processes: Arc::new(RwLock::new(JoinSet::new())),
-            subprocesses: Arc::new(RwLock::new(vec![])),
+            processes: processes.clone(),
+            subprocesses: subprocesses.clone(),
             work_done_progress: Arc::new(RwLock::new(false)),
+            position_encoding: Arc::new(RwLock::new(utils::PositionEncoding::default())),
+            pinned: Arc::new(RwLock::new(HashMap::new())),
+            pending: Arc::new(RwLock::new(HashMap::new())),
+            overlaid: Arc::new(RwLock::new(Vec::new())),
+            outdated: Arc::new(RwLock::new(false)),
+            _shutdown: Arc::new(ShutdownGuard {
+                processes,
+                subprocesses,
+            }),
         }
     }
     /// returns `true` if the root is registered
@@ -76,7 +172,9 @@ impl Backend {
                             .join("owl");
                         tokio::fs::create_dir_all(&target).await.unwrap();
 
-                        write.insert(path.as_std_path().to_path_buf(), target);
+                        write.insert(path.as_std_path().to_path_buf(), target.clone());
+                        drop(write);
+                        self.load_cache(&target).await;
                     }
                     return true;
                 }
@@ -84,19 +182,117 @@ impl Backend {
         }
         false
     }
+    /// serve results of files unchanged since the last analysis;
+    /// if any file is changed, the workspace is marked to be analyzed again
+    async fn load_cache(&self, target: &Path) {
+        let Some(cache) = cache::AnalysisCache::load(target.join("cache.json")).await else {
+            return;
+        };
+        let (fresh, stale) = cache.validate().await;
+        log::info!(
+            "{} files loaded from cache; {} files changed",
+            fresh.0.len(),
+            stale.len()
+        );
+        if !fresh.0.is_empty() {
+            let filenames: Vec<_> = fresh.0.keys().cloned().collect();
+            self.analyzed
+                .write()
+                .await
+                .get_or_insert_with(|| Crate(HashMap::new()))
+                .merge(fresh);
+            notification::analysis_updated(&self.client, filenames).await;
+        }
+        if !stale.is_empty() {
+            if let Some(analyzed) = &mut *self.analyzed.write().await {
+                for filename in &stale {
+                    analyzed.0.remove(filename);
+                }
+            }
+            *self.outdated.write().await = true;
+        }
+    }
+    /// returns `true` if analysis results of the file exist
+    async fn is_analyzed(&self, path: &Path) -> bool {
+        self.analyzed
+            .read()
+            .await
+            .as_ref()
+            .is_some_and(|v| v.0.keys().any(|filename| path == Path::new(filename)))
+    }
+
+    /// write unsaved documents into a new directory under `parent` and returns
+    /// the mapping for rustowlc
+    async fn overlay(&self, parent: &Path) -> (OverlayDir, HashMap<PathBuf, PathBuf>) {
+        let dir = OverlayDir(parent.join(uuid::Uuid::new_v4().to_string()));
+        let mut overlay = HashMap::new();
+        for (path, document) in self.documents.read().await.iter() {
+            if tokio::fs::read_to_string(path)
+                .await
+                .is_ok_and(|v| v == document.text)
+            {
+                continue;
+            }
+            let Ok(path) = path.canonicalize() else {
+                continue;
+            };
+            let file = dir.0.join(format!(
+                "{}.rs",
+                cache::content_hash(path.as_os_str().as_encoded_bytes())
+            ));
+            if tokio::fs::create_dir_all(&dir.0).await.is_ok()
+                && tokio::fs::write(&file, &document.text).await.is_ok()
+            {
+                overlay.insert(path, file);
+            }
+        }
+        (dir, overlay)
+    }
+
+    /// workspace members which cargo would not check again by itself, but have to be
+    /// analyzed: ones with unsaved buffers now or in the last analysis, and ones
+    /// without results
+    ///
+    /// Cargo skips unchanged members, whose results are kept from the last analysis.
+    async fn changed_members(
+        &self,
+        meta: Option<&cargo_metadata::Metadata>,
+        overlay: &HashMap<PathBuf, PathBuf>,
+    ) -> Vec<String> {
+        let Some(meta) = meta else {
+            return Vec::new();
+        };
+        let mut overlaid = self.overlaid.write().await;
+        let analyzed = self.analyzed.read().await;
+        let members = meta
+            .workspace_packages()
+            .into_iter()
+            .filter(|package| {
+                let Some(dir) = package.manifest_path.parent() else {
+                    return true;
+                };
+                let dir = dir.as_std_path();
+                overlay
+                    .keys()
+                    .chain(overlaid.iter())
+                    .any(|v| v.starts_with(dir))
+                    || !analyzed
+                        .iter()
+                        .flat_map(|v| v.0.keys())
+                        .any(|v| Path::new(v).starts_with(dir))
+            })
+            .map(|package| package.name.clone())
+            .collect();
+        *overlaid = overlay.keys().cloned().collect();
+        members
+    }
+
     async fn set_workspace(&self, ws: PathBuf) {
         self.workspaces.write().await.push(ws);
     }
 
     async fn abort_subprocess(&self) {
-        #[cfg(unix)]
-        while let Some(pid) = self.subprocesses.write().await.pop() {
-            if let Some(pid) = pid {
-                unsafe {
-                    libc::killpg(pid.try_into().unwrap(), libc::SIGTERM);
-                }
-            }
-        }
+        abort_subprocesses(&self.subprocesses).await;
     }
 
     async fn analyze(&self) {
@@ -109,9 +305,13 @@ impl Backend {
         self.abort_subprocess().await;
 
         log::info!("start analysis");
-        {
-            *self.status.write().await = progress::AnalysisStatus::Analyzing;
-        }
+        *self.outdated.write().await = false;
+        notification::set_status(
+            &self.client,
+            &self.status,
+            progress::AnalysisStatus::Analyzing,
+        )
+        .await;
         let roots = { self.roots.read().await.clone() };
 
         for (root, target) in roots {
@@ -126,12 +326,17 @@ impl Backend {
                 .unwrap_or(0);
 
             let mut progress_token = None;
+            let (overlay_dir, overlay) = self.overlay(&target.join("overlay")).await;
+            let members = self.changed_members(meta.as_ref(), &overlay).await;
             let package_name = meta.and_then(|v| v.root_package().map(|w| w.name.clone()));
-            if let Some(package_name) = &package_name {
-                log::info!("clear cargo cache");
+            if !members.is_empty() {
+                log::info!("clear cargo cache of {}", members.join(", "));
                 let mut command = process::Command::new("cargo");
+                command.arg("clean");
+                for member in &members {
+                    command.args(["--package", member]);
+                }
                 command
-                    .args(["clean", "--package", package_name])
                     .env("CARGO_TARGET_DIR", &target)
                     .current_dir(&root)
                     .stdout(std::process::Stdio::null())
@@ -167,6 +372,7 @@ impl Backend {
                     "--message-format=json",
                 ])
                 .env("CARGO_TARGET_DIR", &target)
+                .env("RUSTOWL_OVERLAY", serde_json::to_string(&overlay).unwrap())
                 .env_remove("RUSTC_WRAPPER")
                 .current_dir(&root)
                 .stdout(std::process::Stdio::piped())
@@ -185,13 +391,14 @@ impl Backend {
             log::info!("start checking {}", root.display());
             let mut child = command.spawn().unwrap();
             let mut stdout = BufReader::new(child.stdout.take().unwrap()).lines();
-            let analyzed = self.analyzed.clone();
+            let mut results = Results::new(self);
             join.spawn(async move {
                 let mut build_count = 0;
                 while let Ok(Some(line)) = stdout.next_line().await {
                     if let Ok(CargoCheckMessage::CompilerArtifact { .. }) =
                         serde_json::from_str(&line)
                     {
+                        results.notify().await;
                         build_count += 1;
                         log::info!("{build_count} crates checked");
                         if let Some(token) = &progress_token {
@@ -204,17 +411,9 @@ impl Backend {
                                 .await;
                         }
                     }
-                    if let Ok(ws) = serde_json::from_str::<Workspace>(&line) {
-                        let write = &mut *analyzed.write().await;
-                        for krate in ws.0.into_values() {
-                            if let Some(write) = write {
-                                write.merge(krate);
-                            } else {
-                                *write = Some(krate);
-                            }
-                        }
-                    }
+                    results.merge(&line).await;
                 }
+                results.notify().await;
                 if let Some(progress_token) = progress_token {
                     progress_token.finish().await;
                 }
@@ -223,36 +422,38 @@ impl Backend {
             let pid = child.id();
             let subprocesses = self.subprocesses.clone();
             let cache_target = target.join("cache.json");
+            let cache_root = root.clone();
             let analyzed = self.analyzed.clone();
+            let documents = self.documents.clone();
             let status = self.status.clone();
+            let client = self.client.clone();
+            let encoding = *self.position_encoding.read().await;
             join.spawn(async move {
                 let _ = child.wait().await;
                 log::info!("check finished");
+                drop(overlay_dir);
                 let analyzed = &*analyzed.read().await;
                 let mut write = subprocesses.write().await;
                 *write = write.iter().filter(|v| **v != pid).copied().collect();
-                if write.is_empty() {
-                    let mut status = status.write().await;
-                    if *status != progress::AnalysisStatus::Error {
-                        if analyzed.as_ref().map(|v| v.0.len()).unwrap_or(0) == 0 {
-                            *status = progress::AnalysisStatus::Error;
-                        } else {
-                            *status = progress::AnalysisStatus::Finished;
-                        }
-                    }
+                if write.is_empty() && *status.read().await != progress::AnalysisStatus::Error {
+                    let new = if analyzed.as_ref().map(|v| v.0.len()).unwrap_or(0) == 0 {
+                        progress::AnalysisStatus::Error
+                    } else {
+                        progress::AnalysisStatus::Finished
+                    };
+                    notification::set_status(&client, &status, new).await;
                 }
 
-                if let Ok(mut cache_file) = tokio::fs::OpenOptions::new()
-                    .write(true)
-                    .create(true)
-                    .truncate(true)
-                    .open(cache_target)
-                    .await
-                {
-                    cache_file
-                        .write_all(&serde_json::to_vec(analyzed).unwrap())
+                let documents = &*documents.read().await;
+                if let Some(analyzed) = analyzed {
+                    diagnostic::publish(&client, analyzed, documents, encoding).await;
+                }
+
+                if let Some(analyzed) = analyzed {
+                    cache::AnalysisCache::from_crate(analyzed, &cache_root, documents)
                         .await
-                        .ok();
+                        .save(&cache_target)
+                        .await;
                 }
             });
             self.subprocesses.write().await.push(pid);
@@ -272,62 +473,149 @@ impl Backend {
         command.arg("-o/dev/null");
         #[cfg(windows)]
         command.arg("-oNUL");
+        let (overlay_dir, overlay) = self.overlay(&env::temp_dir().join("rustowl-overlay")).await;
         command
             .arg(path.as_ref())
+            .env("RUSTOWL_OVERLAY", serde_json::to_string(&overlay).unwrap())
             .stdout(std::process::Stdio::piped())
             .kill_on_drop(true);
 
         toolchain::set_rustc_env(&mut command, &sysroot);
 
+        log::info!("start analyzing {}", path.as_ref().display());
+        self.spawn_rustowlc(command, Arc::new(overlay_dir)).await;
+    }
+
+    /// re-run rustowlc on the crates compiling the file, as cargo last ran it, with
+    /// unsaved buffers; the whole workspace is checked if the crates are not known yet
+    async fn analyze_crates_of(&self, path: &Path) {
+        let Ok(path) = path.canonicalize() else {
+            return;
+        };
+        let target = self
+            .roots
+            .read()
+            .await
+            .iter()
+            .find(|(root, _)| path.starts_with(root))
+            .map(|(_, target)| target.clone());
+        let invocations = match &target {
+            Some(target) => cache::Invocation::of_file(target, &path).await,
+            None => Vec::new(),
+        };
+        let (Some(target), false) = (target, invocations.is_empty()) else {
+            return self.analyze().await;
+        };
+        if *self.status.read().await == progress::AnalysisStatus::Analyzing {
+            return self.analyze().await;
+        }
+
+        self.processes.write().await.shutdown().await;
+        self.abort_subprocess().await;
+        notification::set_status(
+            &self.client,
+            &self.status,
+            progress::AnalysisStatus::Analyzing,
+        )
+        .await;
+
+        let sysroot = toolchain::get_sysroot().await;
+        let rustowlc_path = toolchain::get_rustowlc_path().await;
+        let (overlay_dir, overlay) = self.overlay(&target.join("overlay")).await;
+        // the next check of the workspace has to clean the crates built from overlays
+        self.overlaid.write().await.extend(overlay.keys().cloned());
+        let overlay_dir = Arc::new(overlay_dir);
+        for invocation in invocations {
+            let mut command = process::Command::new(&rustowlc_path);
+            command
+                .arg(&rustowlc_path) // rustowlc triggers when first arg is the path of itself
+                .args(&invocation.args)
+                .envs(invocation.env)
+                .env("RUSTOWL_OVERLAY", serde_json::to_string(&overlay).unwrap())
+                .current_dir(&invocation.cwd)
+                .stdout(std::process::Stdio::piped())
+                .kill_on_drop(true);
+            toolchain::set_rustc_env(&mut command, &sysroot);
+
+            log::info!("start analyzing crate of {}", path.display());
+            self.spawn_rustowlc(command, overlay_dir.clone()).await;
+        }
+    }
+
+    /// run rustowlc, merging its results as they are printed
+    async fn spawn_rustowlc(&self, mut command: process::Command, overlay_dir: Arc<OverlayDir>) {
         if log::max_level().to_level().is_none() {
             command.stderr(std::process::Stdio::null());
         }
-
-        log::info!("start analyzing {}", path.as_ref().display());
         let mut child = command.spawn().unwrap();
         let mut stdout = BufReader::new(child.stdout.take().unwrap()).lines();
-        let analyzed = self.analyzed.clone();
+        let mut results = Results::new(self);
         let mut join = self.processes.write().await;
 
         join.spawn(async move {
             while let Ok(Some(line)) = stdout.next_line().await {
-                if let Ok(ws) = serde_json::from_str::<Workspace>(&line) {
-                    let write = &mut *analyzed.write().await;
-                    for krate in ws.0.into_values() {
-                        if let Some(write) = write {
-                            write.merge(krate);
-                        } else {
-                            *write = Some(krate);
-                        }
-                    }
-                }
+                results.merge(&line).await;
             }
+            results.notify().await;
         });
 
         let pid = child.id();
         let subprocesses = self.subprocesses.clone();
         let analyzed = self.analyzed.clone();
+        let documents = self.documents.clone();
         let status = self.status.clone();
+        let client = self.client.clone();
+        let encoding = *self.position_encoding.read().await;
         join.spawn(async move {
             let _ = child.wait().await;
             log::info!("analysis finished");
+            drop(overlay_dir);
             let analyzed = &*analyzed.read().await;
             let mut write = subprocesses.write().await;
             *write = write.iter().filter(|v| **v != pid).copied().collect();
-            if write.is_empty() {
-                let mut status = status.write().await;
-                if *status != progress::AnalysisStatus::Error {
-                    if analyzed.as_ref().map(|v| v.0.len()).unwrap_or(0) == 0 {
-                        *status = progress::AnalysisStatus::Error;
-                    } else {
-                        *status = progress::AnalysisStatus::Finished;
-                    }
-                }
+            if write.is_empty() && *status.read().await != progress::AnalysisStatus::Error {
+                let new = if analyzed.as_ref().map(|v| v.0.len()).unwrap_or(0) == 0 {
+                    progress::AnalysisStatus::Error
+                } else {
+                    progress::AnalysisStatus::Finished
+                };
+                notification::set_status(&client, &status, new).await;
+            }
+            if let Some(analyzed) = analyzed {
+                diagnostic::publish(&client, analyzed, &*documents.read().await, encoding).await;
             }
         });
         self.subprocesses.write().await.push(pid);
     }
 
+    /// analyze the document once typing pauses; a newer change restarts the wait
+    async fn schedule_analysis(&self, path: PathBuf, version: i32) {
+        let mut pending = self.pending.write().await;
+        if let Some((_, task)) = pending.remove(&path) {
+            task.abort();
+        }
+        let backend = self.clone();
+        let key = path.clone();
+        let task = tokio::spawn(async move {
+            tokio::time::sleep(tokio::time::Duration::from_millis(500)).await;
+            // later changes schedule another analysis instead of aborting this one
+            {
+                let mut pending = backend.pending.write().await;
+                if pending.get(&path).is_some_and(|(v, _)| *v == version) {
+                    pending.remove(&path);
+                } else {
+                    return;
+                }
+            }
+            if backend.set_roots(&path).await {
+                backend.analyze_crates_of(&path).await;
+            } else {
+                backend.analyze_single_file(&path).await;
+            }
+        });
+        pending.insert(key, (version, task));
+    }
+
     async fn decos(
         &self,
         filepath: &Path,
@@ -336,24 +624,23 @@ impl Backend {
         let mut selected = decoration::SelectLocal::new(position);
         let mut error = progress::AnalysisStatus::Error;
         if let Some(analyzed) = &*self.analyzed.read().await {
+            // only functions enclosing the cursor can own the selected locals
+            let mut enclosing = Vec::new();
             for (filename, file) in analyzed.0.iter() {
                 if filepath == PathBuf::from(filename) {
                     if !file.items.is_empty() {
                         error = progress::AnalysisStatus::Finished;
                     }
-                    for item in &file.items {
-                        utils::mir_visit(item, &mut selected);
-                    }
+                    enclosing.extend(file.functions_at(position));
                 }
             }
+            for item in &enclosing {
+                utils::mir_visit(item, &mut selected);
+            }
 
             let mut calc = decoration::CalcDecos::new(selected.selected().iter().copied());
-            for (filename, file) in analyzed.0.iter() {
-                if filepath == PathBuf::from(filename) {
-                    for item in &file.items {
-                        utils::mir_visit(item, &mut calc);
-                    }
-                }
+            for item in &enclosing {
+                utils::mir_visit(item, &mut calc);
             }
             calc.handle_overlapping();
             let decos = calc.decorations();
@@ -374,13 +661,12 @@ impl Backend {
         let is_analyzed = self.analyzed.read().await.is_some();
         let status = *self.status.read().await;
         if let Some(path) = params.path() {
-            if let Ok(text) = std::fs::read_to_string(&path) {
+            let text = document::read(&*self.documents.read().await, &path).await;
+            if let Some(text) = text {
+                let encoding = *self.position_encoding.read().await;
                 let position = params.position();
-                let pos = Loc(utils::line_char_to_index(
-                    &text,
-                    position.line,
-                    position.character,
-                ));
+                let index = utils::LineIndex::new(&text);
+                let pos = index.index(position.line, position.character, encoding);
                 let (decos, status) = match self.decos(&path, pos).await {
                     Ok(v) => (v, status),
                     Err(e) => (
@@ -392,7 +678,10 @@ impl Backend {
                         },
                     ),
                 };
-                let decorations = decos.into_iter().map(|v| v.to_lsp_range(&text)).collect();
+                let decorations = decos
+                    .into_iter()
+                    .map(|v| v.to_lsp_range(&index, encoding))
+                    .collect();
                 return Ok(decoration::Decorations {
                     is_analyzed,
                     status,
@@ -409,6 +698,277 @@ impl Backend {
         })
     }
 
+    async fn hover_text(&self, path: &Path, position: lsp_types::Position) -> Option<String> {
+        let text = document::read(&*self.documents.read().await, path).await?;
+        let encoding = *self.position_encoding.read().await;
+        let index = utils::LineIndex::new(&text);
+        let pos = index.index(position.line, position.character, encoding);
+        let analyzed = self.analyzed.read().await;
+        let file = analyzed.as_ref()?.0.iter().find_map(|(filename, file)| {
+            if path == PathBuf::from(filename) {
+                Some(file)
+            } else {
+                None
+            }
+        })?;
+        let enclosing: Vec<_> = file.functions_at(pos).collect();
+        hover::hover(&enclosing, pos, &index)
+    }
+
+    /// runs `f` over the analyzed functions of the file overlapping `range`,
+    /// or the whole file if `range` is `None`
+    async fn with_functions<T>(
+        &self,
+        path: &Path,
+        range: Option<lsp_types::Range>,
+        f: impl FnOnce(&[&Function], Range, &str, &utils::LineIndex, utils::PositionEncoding) -> T,
+    ) -> Option<T> {
+        let text = document::read(&*self.documents.read().await, path).await?;
+        let encoding = *self.position_encoding.read().await;
+        let index = utils::LineIndex::new(&text);
+        let visible = match range {
+            Some(range) => Range::new(
+                index.index(range.start.line, range.start.character, encoding),
+                index.index(range.end.line, range.end.character, encoding),
+            )?,
+            None => Range::new(Loc(0), Loc(index.len()))?,
+        };
+        let analyzed = self.analyzed.read().await;
+        let functions: Vec<_> = analyzed
+            .as_ref()?
+            .0
+            .iter()
+            .filter(|(filename, _)| path == PathBuf::from(filename))
+            .flat_map(|(_, file)| file.functions())
+            .filter(|v| v.span.from() <= visible.until() && visible.from() <= v.span.until())
+            .collect();
+        Some(f(&functions, visible, &text, &index, encoding))
+    }
+
+    pub async fn file_decorations(
+        &self,
+        params: decoration::FileDecorationsRequest,
+    ) -> jsonrpc::Result<decoration::FileDecorations> {
+        let is_analyzed = self.analyzed.read().await.is_some();
+        let status = *self.status.read().await;
+        let mut response = decoration::FileDecorations {
+            is_analyzed,
+            status,
+            path: None,
+            etag: None,
+            locals: None,
+        };
+        let Some(path) = params.path() else {
+            return Ok(response);
+        };
+        let documents = self.documents.read().await;
+        let Some(text) = document::read(&documents, &path).await else {
+            return Ok(response);
+        };
+        let encoding = *self.position_encoding.read().await;
+        let analyzed = self.analyzed.read().await;
+        let Some(file) = analyzed.as_ref().and_then(|v| {
+            v.0.iter()
+                .find_map(|(filename, file)| (path == PathBuf::from(filename)).then_some(file))
+        }) else {
+            return Ok(response);
+        };
+
+        // decorations depend on the analysis results and the text, which is identified
+        // by the version of the open document, or by its content if saved
+        let text_version = match documents.get(&path) {
+            Some(document) => document.version.to_string(),
+            None => cache::content_hash(text.as_bytes()),
+        };
+        drop(documents);
+        let etag = format!("{}-{text_version}", file.generation());
+        response.path = Some(path);
+        if params.etag.as_ref() == Some(&etag) {
+            response.etag = Some(etag);
+            return Ok(response);
+        }
+
+        let index = utils::LineIndex::new(&text);
+        let locals = decoration::file_decorations(file)
+            .into_iter()
+            .map(
+                |(local, selectable, decorations)| decoration::LocalDecorations {
+                    local,
+                    selectable: selectable
+                        .into_iter()
+                        .map(|v| index.lsp_range(v, encoding))
+                        .collect(),
+                    decorations: decorations
+                        .into_iter()
+                        .map(|v| v.to_lsp_range(&index, encoding))
+                        .collect(),
+                },
+            )
+            .collect();
+        response.etag = Some(etag);
+        response.locals = Some(locals);
+        Ok(response)
+    }
+
+    /// pin (or unpin if `pin` is `false`) locals selected at the position,
+    /// and returns decorations of the pinned locals
+    async fn update_pinned(&self, params: pin::PinRequest, pin: bool) -> pin::Pinned {
+        let mut response = pin::Pinned {
+            status: *self.status.read().await,
+            path: None,
+            locals: Vec::new(),
+            decorations: Vec::new(),
+            conflicts: Vec::new(),
+        };
+        let Some(path) = params.path() else {
+            return response;
+        };
+        let Some(text) = document::read(&*self.documents.read().await, &path).await else {
+            return response;
+        };
+        let encoding = *self.position_encoding.read().await;
+        let index = utils::LineIndex::new(&text);
+        let analyzed = self.analyzed.read().await;
+        let Some(file) = analyzed.as_ref().and_then(|v| {
+            v.0.iter()
+                .find_map(|(filename, file)| (path == PathBuf::from(filename)).then_some(file))
+        }) else {
+            return response;
+        };
+
+        let locals = {
+            let mut pinned = self.pinned.write().await;
+            let locals = pinned.entry(path.clone()).or_default();
+            match params.position {
+                Some(position) => {
+                    let pos = index.index(position.line, position.character, encoding);
+                    let mut selected = decoration::SelectLocal::new(pos);
+                    for func in file.functions_at(pos) {
+                        utils::mir_visit(func, &mut selected);
+                    }
+                    for local in selected.selected() {
+                        if !pin {
+                            locals.retain(|v| *v != local);
+                        } else if !locals.contains(&local) {
+                            locals.push(local);
+                        }
+                    }
+                }
+                None if !pin => locals.clear(),
+                None => {}
+            }
+            locals.clone()
+        };
+
+        let functions: Vec<_> = file.functions().collect();
+        let (locals, decorations, conflicts) = pin::pinned(&functions, &locals);
+        response.path = Some(path);
+        response.locals = locals;
+        response.decorations = decorations
+            .into_iter()
+            .map(|v| pin::PinnedDeco {
+                tag: v.tag,
+                deco: v.deco.to_lsp_range(&index, encoding),
+            })
+            .collect();
+        response.conflicts = conflicts
+            .into_iter()
+            .map(|v| pin::Conflict {
+                range: index.lsp_range(v.range, encoding),
+                hover_text: v.hover_text,
+            })
+            .collect();
+        response
+    }
+
+    pub async fn pin(&self, params: pin::PinRequest) -> jsonrpc::Result<pin::Pinned> {
+        Ok(self.update_pinned(params, true).await)
+    }
+
+    pub async fn unpin(&self, params: pin::PinRequest) -> jsonrpc::Result<pin::Pinned> {
+        Ok(self.update_pinned(params, false).await)
+    }
+
+    pub async fn borrowers(
+        &self,
+        params: decoration::CursorRequest,
+    ) -> jsonrpc::Result<borrower::Borrowers> {
+        let mut response = borrower::Borrowers {
+            status: *self.status.read().await,
+            path: None,
+            loans: Vec::new(),
+            referents: Vec::new(),
+        };
+        let Some(path) = params.path() else {
+            return Ok(response);
+        };
+        let Some(text) = document::read(&*self.documents.read().await, &path).await else {
+            return Ok(response);
+        };
+        let encoding = *self.position_encoding.read().await;
+        let index = utils::LineIndex::new(&text);
+        let position = params.position();
+        let pos = index.index(position.line, position.character, encoding);
+        let analyzed = self.analyzed.read().await;
+        let Some(file) = analyzed.as_ref().and_then(|v| {
+            v.0.iter()
+                .find_map(|(filename, file)| (path == PathBuf::from(filename)).then_some(file))
+        }) else {
+            return Ok(response);
+        };
+        let functions: Vec<_> = file.functions_at(pos).collect();
+        let mut selected = decoration::SelectLocal::new(pos);
+        for func in &functions {
+            utils::mir_visit(func, &mut selected);
+        }
+        let (loans, referents) = borrower::borrowers(&functions, &selected.selected());
+        response.path = Some(path);
+        response.loans = loans
+            .iter()
+            .map(|v| v.to_lsp_range(&index, encoding))
+            .collect();
+        response.referents = referents
+            .iter()
+            .map(|v| v.to_lsp_range(&index, encoding))
+            .collect();
+        Ok(response)
+    }
+
+    pub async fn function_overview(
+        &self,
+        params: decoration::CursorRequest,
+    ) -> jsonrpc::Result<overview::FunctionOverview> {
+        let mut response = overview::FunctionOverview {
+            status: *self.status.read().await,
+            path: None,
+            function: None,
+            variables: Vec::new(),
+        };
+        let Some(path) = params.path() else {
+            return Ok(response);
+        };
+        let Some(text) = document::read(&*self.documents.read().await, &path).await else {
+            return Ok(response);
+        };
+        let encoding = *self.position_encoding.read().await;
+        let index = utils::LineIndex::new(&text);
+        let position = params.position();
+        let pos = index.index(position.line, position.character, encoding);
+        let analyzed = self.analyzed.read().await;
+        let Some(file) = analyzed.as_ref().and_then(|v| {
+            v.0.iter()
+                .find_map(|(filename, file)| (path == PathBuf::from(filename)).then_some(file))
+        }) else {
+            return Ok(response);
+        };
+        response.path = Some(path);
+        if let Some(func) = file.innermost_at(pos) {
+            response.function = Some(func.path.clone());
+            response.variables = overview::variables(func, &index, encoding);
+        }
+        Ok(response)
+    }
+
     pub async fn check(path: impl AsRef<Path>) -> bool {
         let path = path.as_ref();
         let (service, _) = LspService::build(Backend::new).finish();
@@ -432,18 +992,6 @@ impl Backend {
     }
 }
 
-impl Drop for Backend {
-    fn drop(&mut self) {
-        tokio::task::block_in_place(|| {
-            tokio::runtime::Handle::current().block_on(async {
-                if let Err(err) = self.shutdown().await {
-                    log::error!("failed to shutdown the server gracefully: {err}");
-                };
-            });
-        });
-    }
-}
-
 #[tower_lsp::async_trait]
 impl LanguageServer for Backend {
     async fn initialize(
@@ -470,9 +1018,50 @@ impl LanguageServer for Backend {
             }),
             ..Default::default()
         };
+        // the first encoding the client prefers among ones supported
+        let (position_encoding, encoding) = params
+            .capabilities
+            .general
+            .as_ref()
+            .and_then(|v| v.position_encodings.as_ref())
+            .and_then(|v| {
+                v.iter().find_map(|kind| match kind.as_str() {
+                    "utf-8" => Some((kind.clone(), utils::PositionEncoding::Utf8)),
+                    "utf-16" => Some((kind.clone(), utils::PositionEncoding::Utf16)),
+                    "utf-32" => Some((kind.clone(), utils::PositionEncoding::Utf32)),
+                    _ => None,
+                })
+            })
+            .unwrap_or((
+                lsp_types::PositionEncodingKind::UTF16,
+                utils::PositionEncoding::Utf16,
+            ));
+        *self.position_encoding.write().await = encoding;
         let server_cap = lsp_types::ServerCapabilities {
+            position_encoding: Some(position_encoding),
             text_document_sync: Some(lsp_types::TextDocumentSyncCapability::Options(sync_options)),
             workspace: Some(workspace_cap),
+            hover_provider: Some(lsp_types::HoverProviderCapability::Simple(true)),
+            document_highlight_provider: Some(lsp_types::OneOf::Left(true)),
+            inlay_hint_provider: Some(lsp_types::OneOf::Left(true)),
+            code_action_provider: Some(lsp_types::CodeActionProviderCapability::Simple(true)),
+            code_lens_provider: Some(lsp_types::CodeLensOptions {
+                resolve_provider: Some(false),
+            }),
+            execute_command_provider: Some(lsp_types::ExecuteCommandOptions {
+                commands: vec![code_lens::OVERVIEW_COMMAND.to_owned()],
+                ..Default::default()
+            }),
+            semantic_tokens_provider: Some(
+                lsp_types::SemanticTokensServerCapabilities::SemanticTokensOptions(
+                    lsp_types::SemanticTokensOptions {
+                        legend: semantic::legend(),
+                        range: Some(true),
+                        full: Some(lsp_types::SemanticTokensFullOptions::Bool(true)),
+                        ..Default::default()
+                    },
+                ),
+            ),
             ..Default::default()
         };
         let init_res = lsp_types::InitializeResult {
@@ -515,8 +1104,17 @@ impl LanguageServer for Backend {
     async fn did_open(&self, params: lsp_types::DidOpenTextDocumentParams) {
         if let Ok(path) = params.text_document.uri.to_file_path() {
             if params.text_document.language_id == "rust" {
+                self.documents.write().await.insert(
+                    path.clone(),
+                    document::Document::new(
+                        params.text_document.text,
+                        params.text_document.version,
+                    ),
+                );
                 if self.set_roots(&path).await {
-                    self.analyze().await;
+                    if !self.is_analyzed(&path).await || *self.outdated.read().await {
+                        self.analyze().await;
+                    }
                 } else {
                     self.analyze_single_file(&path).await;
                 }
@@ -533,9 +1131,233 @@ impl LanguageServer for Backend {
             }
         }
     }
-    async fn did_change(&self, _params: lsp_types::DidChangeTextDocumentParams) {
-        *self.analyzed.write().await = None;
-        self.processes.write().await.shutdown().await;
+    async fn did_change(&self, params: lsp_types::DidChangeTextDocumentParams) {
+        let Ok(path) = params.text_document.uri.to_file_path() else {
+            return;
+        };
+        let version = params.text_document.version;
+        let edits = {
+            let mut documents = self.documents.write().await;
+            let Some(document) = documents.get_mut(&path) else {
+                return;
+            };
+            document.apply(
+                params.content_changes,
+                version,
+                *self.position_encoding.read().await,
+            )
+        };
+        // keep results at their new position; edited functions are marked stale
+        if let Some(analyzed) = &mut *self.analyzed.write().await {
+            for (filename, file) in analyzed.0.iter_mut() {
+                if path == Path::new(filename) {
+                    for edit in &edits {
+                        file.edit(edit.from, edit.until, edit.len);
+                    }
+                }
+            }
+        }
+        self.schedule_analysis(path, version).await;
+    }
+    async fn did_close(&self, params: lsp_types::DidCloseTextDocumentParams) {
+        let Ok(path) = params.text_document.uri.to_file_path() else {
+            return;
+        };
+        self.pinned.write().await.remove(&path);
+        let Some(document) = self.documents.write().await.remove(&path) else {
+            return;
+        };
+        // results of discarded changes are replaced with those of the file
+        let saved = tokio::fs::read_to_string(&path)
+            .await
+            .is_ok_and(|v| v == document.text);
+        if !saved {
+            if self.set_roots(&path).await {
+                self.analyze().await;
+            } else {
+                self.analyze_single_file(&path).await;
+            }
+        }
+    }
+
+    async fn hover(
+        &self,
+        params: lsp_types::HoverParams,
+    ) -> jsonrpc::Result<Option<lsp_types::Hover>> {
+        let params = params.text_document_position_params;
+        let Ok(path) = params.text_document.uri.to_file_path() else {
+            return Ok(None);
+        };
+        Ok(self
+            .hover_text(&path, params.position)
+            .await
+            .map(|value| lsp_types::Hover {
+                contents: lsp_types::HoverContents::Markup(lsp_types::MarkupContent {
+                    kind: lsp_types::MarkupKind::Markdown,
+                    value,
+                }),
+                range: None,
+            }))
+    }
+
+    async fn document_highlight(
+        &self,
+        params: lsp_types::DocumentHighlightParams,
+    ) -> jsonrpc::Result<Option<Vec<lsp_types::DocumentHighlight>>> {
+        let params = params.text_document_position_params;
+        let Ok(path) = params.text_document.uri.to_file_path() else {
+            return Ok(None);
+        };
+        let Some(text) = document::read(&*self.documents.read().await, &path).await else {
+            return Ok(None);
+        };
+        let encoding = *self.position_encoding.read().await;
+        let index = utils::LineIndex::new(&text);
+        let pos = index.index(params.position.line, params.position.character, encoding);
+        let Ok(decos) = self.decos(&path, pos).await else {
+            return Ok(None);
+        };
+        Ok(Some(
+            decos
+                .iter()
+                .filter_map(|v| v.to_lsp_range(&index, encoding).to_highlight())
+                .collect(),
+        ))
+    }
+
+    async fn code_action(
+        &self,
+        params: lsp_types::CodeActionParams,
+    ) -> jsonrpc::Result<Option<lsp_types::CodeActionResponse>> {
+        let uri = params.text_document.uri;
+        let Ok(path) = uri.to_file_path() else {
+            return Ok(None);
+        };
+        let Some(text) = document::read(&*self.documents.read().await, &path).await else {
+            return Ok(None);
+        };
+        let encoding = *self.position_encoding.read().await;
+        let index = utils::LineIndex::new(&text);
+        let (start, end) = (params.range.start, params.range.end);
+        let from = index.index(start.line, start.character, encoding);
+        let until = index.index(end.line, end.character, encoding);
+        let analyzed = self.analyzed.read().await;
+        let Some(file) = analyzed.as_ref().and_then(|v| {
+            v.0.iter()
+                .find_map(|(filename, file)| (path == PathBuf::from(filename)).then_some(file))
+        }) else {
+            return Ok(None);
+        };
+        let enclosing: Vec<_> = file.functions_at(from).collect();
+        let source = code_action::Source {
+            uri: &uri,
+            text: &text,
+            index: &index,
+            encoding,
+        };
+        Ok(Some(code_action::code_actions(
+            &enclosing, from, until, &source,
+        )))
+    }
+
+    async fn code_lens(
+        &self,
+        params: lsp_types::CodeLensParams,
+    ) -> jsonrpc::Result<Option<Vec<lsp_types::CodeLens>>> {
+        let uri = params.text_document.uri;
+        let Ok(path) = uri.to_file_path() else {
+            return Ok(None);
+        };
+        let Some(text) = document::read(&*self.documents.read().await, &path).await else {
+            return Ok(None);
+        };
+        let encoding = *self.position_encoding.read().await;
+        let index = utils::LineIndex::new(&text);
+        let analyzed = self.analyzed.read().await;
+        let functions: Vec<_> = analyzed
+            .iter()
+            .flat_map(|v| &v.0)
+            .filter(|(filename, _)| path == PathBuf::from(filename))
+            .flat_map(|(_, file)| file.functions())
+            .collect();
+        Ok(Some(code_lens::code_lenses(
+            &functions, &uri, &index, encoding,
+        )))
+    }
+
+    async fn execute_command(
+        &self,
+        params: lsp_types::ExecuteCommandParams,
+    ) -> jsonrpc::Result<Option<serde_json::Value>> {
+        if params.command != code_lens::OVERVIEW_COMMAND {
+            return Err(jsonrpc::Error::method_not_found());
+        }
+        let Some(request) = params
+            .arguments
+            .into_iter()
+            .next()
+            .and_then(|v| serde_json::from_value::<decoration::CursorRequest>(v).ok())
+        else {
+            return Err(jsonrpc::Error::invalid_params(
+                "expected a document and a position",
+            ));
+        };
+        let overview = self.function_overview(request).await?;
+        Ok(Some(serde_json::to_value(overview).unwrap()))
+    }
+
+    async fn inlay_hint(
+        &self,
+        params: lsp_types::InlayHintParams,
+    ) -> jsonrpc::Result<Option<Vec<lsp_types::InlayHint>>> {
+        let Ok(path) = params.text_document.uri.to_file_path() else {
+            return Ok(None);
+        };
+        Ok(self
+            .with_functions(
+                &path,
+                Some(params.range),
+                |functions, visible, _, index, encoding| {
+                    inlay::inlay_hints(functions, visible, index, encoding)
+                },
+            )
+            .await)
+    }
+
+    async fn semantic_tokens_full(
+        &self,
+        params: lsp_types::SemanticTokensParams,
+    ) -> jsonrpc::Result<Option<lsp_types::SemanticTokensResult>> {
+        let Ok(path) = params.text_document.uri.to_file_path() else {
+            return Ok(None);
+        };
+        Ok(self
+            .with_functions(&path, None, semantic::tokens)
+            .await
+            .map(|data| {
+                lsp_types::SemanticTokensResult::Tokens(lsp_types::SemanticTokens {
+                    result_id: None,
+                    data,
+                })
+            }))
+    }
+
+    async fn semantic_tokens_range(
+        &self,
+        params: lsp_types::SemanticTokensRangeParams,
+    ) -> jsonrpc::Result<Option<lsp_types::SemanticTokensRangeResult>> {
+        let Ok(path) = params.text_document.uri.to_file_path() else {
+            return Ok(None);
+        };
+        Ok(self
+            .with_functions(&path, Some(params.range), semantic::tokens)
+            .await
+            .map(|data| {
+                lsp_types::SemanticTokensRangeResult::Tokens(lsp_types::SemanticTokens {
+                    result_id: None,
+                    data,
+                })
+            }))
     }
 
     async fn shutdown(&self) -> jsonrpc::Result<()> {
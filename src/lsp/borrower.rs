@@ -0,0 +1,105 @@
+use crate::{lsp::progress, models::*, utils};
+use std::path::PathBuf;
+use tower_lsp::lsp_types;
+
+/// A variable holding the reference of a loan
+#[derive(serde::Serialize, Clone, Debug)]
+pub struct Holder<R = Range> {
+    pub local: FnLocal,
+    pub name: String,
+    /// where the variable keeps the loan alive
+    pub live: Vec<R>,
+}
+
+#[derive(serde::Serialize, Clone, Debug)]
+pub struct Loan<R = Range> {
+    /// borrowed local
+    pub local: FnLocal,
+    /// borrowed place, e.g. `s.a`
+    pub place: String,
+    pub mutable: bool,
+    /// where the place is borrowed
+    pub range: R,
+    pub holders: Vec<Holder<R>>,
+}
+impl Loan<Range> {
+    pub fn to_lsp_range(
+        &self,
+        index: &utils::LineIndex,
+        encoding: utils::PositionEncoding,
+    ) -> Loan<lsp_types::Range> {
+        let to_lsp_range = |range: Range| index.lsp_range(range, encoding);
+        Loan {
+            local: self.local,
+            place: self.place.clone(),
+            mutable: self.mutable,
+            range: to_lsp_range(self.range),
+            holders: self
+                .holders
+                .iter()
+                .map(|v| Holder {
+                    local: v.local,
+                    name: v.name.clone(),
+                    live: v.live.iter().copied().map(to_lsp_range).collect(),
+                })
+                .collect(),
+        }
+    }
+}
+
+#[derive(serde::Serialize, Clone, Debug)]
+pub struct Borrowers {
+    pub status: progress::AnalysisStatus,
+    pub path: Option<PathBuf>,
+    /// loans of the selected variables, with the references holding them
+    pub loans: Vec<Loan<lsp_types::Range>>,
+    /// loans held by the selected variables, i.e. what they point to
+    pub referents: Vec<Loan<lsp_types::Range>>,
+}
+
+/// returns (loans of the locals, loans held by the locals)
+pub fn borrowers(functions: &[&Function], locals: &[FnLocal]) -> (Vec<Loan>, Vec<Loan>) {
+    let name = |local: FnLocal| {
+        functions
+            .iter()
+            .flat_map(|v| &v.decls)
+            .find_map(|decl| match decl {
+                MirDecl::User { local: l, name, .. } if *l == local => Some(name.clone()),
+                _ => None,
+            })
+    };
+
+    let mut loans: Vec<Loan> = Vec::new();
+    let mut referents: Vec<Loan> = Vec::new();
+    for loan in functions.iter().flat_map(|v| &v.loans) {
+        let borrowed = locals.contains(&loan.local);
+        let held = loan.holders.iter().any(|v| locals.contains(&v.local));
+        if !borrowed && !held {
+            continue;
+        }
+        let loan = Loan {
+            local: loan.local,
+            place: loan.place.path.clone(),
+            mutable: loan.mutable,
+            range: loan.range,
+            holders: loan
+                .holders
+                .iter()
+                .filter_map(|v| {
+                    name(v.local).map(|name| Holder {
+                        local: v.local,
+                        name,
+                        live: v.live.clone(),
+                    })
+                })
+                .collect(),
+        };
+        if borrowed {
+            loans.push(loan.clone());
+        }
+        if held {
+            referents.push(loan);
+        }
+    }
+    (loans, referents)
+}
@@ -0,0 +1,556 @@
+use crate::{lsp::decoration, models::*, utils};
+use std::collections::HashMap;
+use tower_lsp::lsp_types;
+
+/// what code actions need to know about the document
+pub struct Source<'a> {
+    pub uri: &'a lsp_types::Url,
+    pub text: &'a str,
+    pub index: &'a utils::LineIndex,
+    pub encoding: utils::PositionEncoding,
+}
+impl Source<'_> {
+    fn position(&self, loc: Loc) -> lsp_types::Position {
+        self.index.lsp_position(loc, self.encoding)
+    }
+    fn line(&self, loc: Loc) -> u32 {
+        self.index.line_char(loc, self.encoding).0
+    }
+    /// the line with its line break, which is `\n`, `\r\n` or none on the last line
+    fn line_text(&self, line: u32) -> &str {
+        let from = self.index.byte(self.index.index(line, 0, self.encoding));
+        let until = self.index.byte(self.index.line_end(line) + 1);
+        &self.text[from as usize..until as usize]
+    }
+    /// the line break ending the line, or `\n` on the last line
+    fn line_break(&self, line: u32) -> &str {
+        let text = self.line_text(line);
+        match text.strip_suffix('\n') {
+            Some(content) => &text[content.trim_end_matches('\r').len()..],
+            None => "\n",
+        }
+    }
+    /// the end of the line before its line break
+    fn content_end(&self, line: u32) -> Loc {
+        let end = self.index.line_end(line);
+        if end.0 < self.index.len() && self.line_text(line).ends_with("\r\n") {
+            end - 1
+        } else {
+            end
+        }
+    }
+    fn indent(&self, line: u32) -> &str {
+        let text = self.line_text(line);
+        &text[..text.len() - text.trim_start().len()]
+    }
+    /// how many more blocks are open at `until` than at `from`, ignoring braces in
+    /// comments and literals
+    fn depth(&self, from: Loc, until: Loc) -> i32 {
+        let (from, until) = (self.index.byte(from), self.index.byte(until));
+        let mut depth = 0;
+        let mut chars = self.text[from as usize..until.max(from) as usize]
+            .chars()
+            .peekable();
+        // the identifier before `c`, to tell raw strings from identifiers ending with `r`
+        let mut word = String::new();
+        while let Some(c) = chars.next() {
+            match c {
+                '{' => depth += 1,
+                '}' => depth -= 1,
+                '/' if chars.peek() == Some(&'/') => {
+                    chars.by_ref().find(|v| *v == '\n');
+                }
+                '/' if chars.peek() == Some(&'*') => {
+                    chars.next();
+                    let mut nested = 1;
+                    while 0 < nested {
+                        match (chars.next(), chars.peek()) {
+                            (Some('/'), Some('*')) => nested += 1,
+                            (Some('*'), Some('/')) => nested -= 1,
+                            (None, _) => break,
+                            _ => continue,
+                        }
+                        chars.next();
+                    }
+                }
+                '"' => {
+                    while let Some(v) = chars.next() {
+                        match v {
+                            '\\' => {
+                                chars.next();
+                            }
+                            '"' => break,
+                            _ => {}
+                        }
+                    }
+                }
+                // raw strings, e.g. `r#"..."#`, end with as many `#`s as they start with
+                'r' if matches!(word.as_str(), "" | "b" | "c")
+                    && matches!(chars.peek(), Some('#' | '"')) =>
+                {
+                    let mut hashes = 0;
+                    while chars.next_if_eq(&'#').is_some() {
+                        hashes += 1;
+                    }
+                    if chars.next_if_eq(&'"').is_some() {
+                        let mut closing = None;
+                        for v in chars.by_ref() {
+                            if v == '"' {
+                                closing = Some(0);
+                            } else if v == '#' && closing.is_some() {
+                                closing = closing.map(|n| n + 1);
+                            } else {
+                                closing = None;
+                            }
+                            if closing == Some(hashes) {
+                                break;
+                            }
+                        }
+                    }
+                }
+                // a character literal, not a lifetime or a label
+                '\'' => {
+                    let mut ahead = chars.clone();
+                    match (ahead.next(), ahead.next()) {
+                        (Some('\\'), _) => {
+                            chars.next();
+                            chars.next();
+                            chars.by_ref().find(|v| *v == '\'');
+                        }
+                        (Some(_), Some('\'')) => {
+                            chars.next();
+                            chars.next();
+                        }
+                        _ => {}
+                    }
+                }
+                _ => {}
+            }
+            if c.is_alphanumeric() || c == '_' {
+                word.push(c);
+            } else {
+                word.clear();
+            }
+        }
+        depth
+    }
+    fn action(&self, title: String, edit: lsp_types::TextEdit) -> lsp_types::CodeActionOrCommand {
+        lsp_types::CodeActionOrCommand::CodeAction(lsp_types::CodeAction {
+            title,
+            kind: Some(lsp_types::CodeActionKind::QUICKFIX),
+            edit: Some(lsp_types::WorkspaceEdit {
+                changes: Some(HashMap::from([(self.uri.clone(), vec![edit])])),
+                ..Default::default()
+            }),
+            ..Default::default()
+        })
+    }
+}
+
+/// `.clone()` at a move of the place under the cursor which is used after the move
+fn clone_at_move(
+    functions: &[&Function],
+    decos: &[decoration::Deco],
+    cursor: Loc,
+    source: &Source,
+) -> Vec<lsp_types::CodeActionOrCommand> {
+    let mut actions = Vec::new();
+    for deco in decos {
+        let decoration::Deco::Move {
+            local,
+            place,
+            range,
+            ..
+        } = deco
+        else {
+            continue;
+        };
+        if cursor < range.from() || range.until() < cursor {
+            continue;
+        }
+        let conflicts = functions.iter().flat_map(|v| &v.errors).any(|error| {
+            matches!(error, MirBorrowError::Move { local: l, moved, .. }
+                if l == local && moved.contains(range))
+        });
+        if conflicts {
+            let position = source.position(range.until());
+            actions.push(source.action(
+                format!("Clone `{}` instead of moving it", place.path),
+                lsp_types::TextEdit {
+                    range: lsp_types::Range::new(position, position),
+                    new_text: ".clone()".to_owned(),
+                },
+            ));
+        }
+    }
+    actions
+}
+
+/// `drop(x);` right after the last use of the variable under the cursor,
+/// if it would be dropped later at the end of its scope
+fn drop_after_last_use(
+    functions: &[&Function],
+    decos: &[decoration::Deco],
+    locals: &[FnLocal],
+    source: &Source,
+) -> Vec<lsp_types::CodeActionOrCommand> {
+    let mut actions = Vec::new();
+    for func in functions {
+        for decl in &func.decls {
+            let MirDecl::User {
+                local,
+                name,
+                span,
+                drop: true,
+                must_live_at,
+                ..
+            } = decl
+            else {
+                continue;
+            };
+            if !locals.contains(local) {
+                continue;
+            }
+            // uses drawn for the variable, and borrows of it, which have to end before
+            // it is dropped
+            let mut used: RangeSet = must_live_at.iter().copied().collect();
+            let mut moved = false;
+            for deco in decos.iter().filter(|v| v.local() == *local) {
+                match deco {
+                    decoration::Deco::Move { .. } => moved = true,
+                    decoration::Deco::ImmBorrow { range, .. }
+                    | decoration::Deco::MutBorrow { range, .. }
+                    | decoration::Deco::Outlive { range, .. } => used.insert(*range),
+                    _ => {}
+                }
+            }
+            // the drop at the end of the scope declaring the variable
+            let dropped = func
+                .basic_blocks
+                .iter()
+                .filter_map(|bb| match bb.terminator {
+                    Some(MirTerminator::Drop { local: l, range }) if l == *local => {
+                        Some(range.until())
+                    }
+                    _ => None,
+                })
+                .max();
+            let (false, Some(last_use), Some(dropped)) =
+                (moved, used.ranges().last().map(|v| v.until()), dropped)
+            else {
+                continue;
+            };
+            let line = source.line(last_use);
+            if source.line(dropped) <= line + 1 {
+                continue;
+            }
+            let line_end = source.content_end(line);
+            // inside a nested block or loop body, the drop would run too early or repeatedly
+            if 0 < source.depth(span.until(), line_end) {
+                continue;
+            }
+            let end = source.position(line_end);
+            actions.push(source.action(
+                format!("Drop `{name}` after its last use"),
+                lsp_types::TextEdit {
+                    range: lsp_types::Range::new(end, end),
+                    new_text: format!(
+                        "{}{}drop({name});",
+                        source.line_break(line),
+                        source.indent(line)
+                    ),
+                },
+            ));
+            return actions;
+        }
+    }
+    actions
+}
+
+/// wrap the selected lines in a block, so that borrows created in them end there,
+/// unless variables declared in them are used after them
+fn wrap_in_block(
+    functions: &[&Function],
+    decos: &[decoration::Deco],
+    from: Loc,
+    until: Loc,
+    source: &Source,
+) -> Vec<lsp_types::CodeActionOrCommand> {
+    let borrows = decos.iter().any(|deco| match deco {
+        decoration::Deco::ImmBorrow { range, .. } | decoration::Deco::MutBorrow { range, .. } => {
+            from <= range.from() && range.until() <= until
+        }
+        _ => false,
+    });
+    if until <= from || !borrows {
+        return Vec::new();
+    }
+    let first = source.line(from);
+    // a selection of whole lines ends at the start of the next one
+    let last = match source.index.line_char(until, source.encoding) {
+        (line, 0) if first < line => line - 1,
+        (line, _) => line,
+    };
+    let block_from = source.index.index(first, 0, source.encoding);
+    let block_until = source.index.line_end(last);
+    let escapes = functions.iter().flat_map(|v| &v.decls).any(|decl| {
+        let MirDecl::User {
+            span,
+            lives,
+            must_live_at,
+            ..
+        } = decl
+        else {
+            return false;
+        };
+        block_from <= span.from()
+            && span.until() <= block_until
+            && lives
+                .iter()
+                .chain(must_live_at)
+                .any(|v| block_until < v.until())
+    });
+    if escapes {
+        return Vec::new();
+    }
+    let (indent, line_break) = (source.indent(first), source.line_break(first));
+    let mut new_text = format!("{indent}{{{line_break}");
+    for line in first..=last {
+        let text = source.line_text(line);
+        if !text.trim().is_empty() {
+            new_text.push_str("    ");
+        }
+        new_text.push_str(text);
+    }
+    // the last line of the text may have no line break
+    if !new_text.ends_with('\n') {
+        new_text.push_str(line_break);
+    }
+    new_text.push_str(&format!("{indent}}}{line_break}"));
+    vec![source.action(
+        "Wrap in a block to end borrows earlier".to_owned(),
+        lsp_types::TextEdit {
+            range: lsp_types::Range::new(
+                lsp_types::Position::new(first, 0),
+                lsp_types::Position::new(last + 1, 0),
+            ),
+            new_text,
+        },
+    )]
+}
+
+/// quick fixes for the selection `from..until`, computed from decorations of every local
+/// in the enclosing functions
+pub fn code_actions(
+    functions: &[&Function],
+    from: Loc,
+    until: Loc,
+    source: &Source,
+) -> Vec<lsp_types::CodeActionOrCommand> {
+    let mut selected = decoration::SelectLocal::new(from);
+    for func in functions {
+        utils::mir_visit(func, &mut selected);
+    }
+    let locals = functions.iter().flat_map(|v| &v.decls).map(|v| v.local());
+    let mut calc = decoration::CalcDecos::new(locals);
+    for func in functions {
+        utils::mir_visit(func, &mut calc);
+    }
+    let decos = calc.decorations();
+
+    let mut actions = clone_at_move(functions, &decos, from, source);
+    actions.extend(drop_after_last_use(
+        functions,
+        &decos,
+        &selected.selected(),
+        source,
+    ));
+    actions.extend(wrap_in_block(functions, &decos, from, until, source));
+    actions
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{range, user_decl};
+
+    /// location of the first `pattern` in the ASCII text
+    fn loc(text: &str, pattern: &str) -> Loc {
+        Loc(text.find(pattern).unwrap() as u32)
+    }
+
+    fn decl(id: u32, name: &str, span: Range, lives: Range, drop_range: Vec<Range>) -> MirDecl {
+        user_decl(
+            FnLocal::new(id, 1),
+            name,
+            "Vec<i32>",
+            span,
+            vec![lives],
+            drop_range,
+        )
+    }
+
+    fn function(decls: Vec<MirDecl>) -> Function {
+        Function {
+            decls,
+            ..crate::testing::function(1, "krate", "krate::f", range(0, 100))
+        }
+    }
+
+    fn edits(actions: Vec<lsp_types::CodeActionOrCommand>) -> Vec<lsp_types::TextEdit> {
+        actions
+            .into_iter()
+            .flat_map(|action| match action {
+                lsp_types::CodeActionOrCommand::CodeAction(action) => action
+                    .edit
+                    .unwrap()
+                    .changes
+                    .unwrap()
+                    .into_values()
+                    .flatten(),
+                lsp_types::CodeActionOrCommand::Command(_) => unreachable!(),
+            })
+            .collect()
+    }
+
+    fn with_source<T>(text: &str, f: impl FnOnce(&Source) -> T) -> T {
+        let uri = lsp_types::Url::parse("file:///lib.rs").unwrap();
+        let index = utils::LineIndex::new(text);
+        f(&Source {
+            uri: &uri,
+            text,
+            index: &index,
+            encoding: utils::PositionEncoding::Utf16,
+        })
+    }
+
+    #[test]
+    fn wrap_whole_lines_unless_bindings_escape() {
+        let text = "fn f() {\n    let v = vec![1];\n    let r = &v;\n    g(r);\n    h(&v);\n}\n";
+        let borrow = decoration::Deco::ImmBorrow {
+            local: FnLocal::new(1, 1),
+            place: MirPlace::new("v", Vec::new()),
+            range: range(loc(text, "&v"), loc(text, ";\n    g")),
+            hover_text: String::new(),
+            overlapped: false,
+        };
+        let r = |lives_until| {
+            decl(
+                2,
+                "r",
+                range(loc(text, "r ="), loc(text, " = &")),
+                range(loc(text, "r ="), lives_until),
+                Vec::new(),
+            )
+        };
+        // lines 2 and 3 selected up to the start of line 4
+        let (from, until) = (loc(text, "    let r"), loc(text, "    h("));
+        let wrap = |r| {
+            with_source(text, |source| {
+                edits(wrap_in_block(
+                    &[&function(vec![r])],
+                    std::slice::from_ref(&borrow),
+                    from,
+                    until,
+                    source,
+                ))
+            })
+        };
+
+        let edits = wrap(r(loc(text, ");\n    h")));
+        assert_eq!(edits.len(), 1);
+        assert_eq!(
+            edits[0].range,
+            lsp_types::Range::new(
+                lsp_types::Position::new(2, 0),
+                lsp_types::Position::new(4, 0)
+            )
+        );
+        assert_eq!(
+            edits[0].new_text,
+            "    {\n        let r = &v;\n        g(r);\n    }\n"
+        );
+
+        // `r` used in line 4
+        assert!(wrap(r(loc(text, ");\n}"))).is_empty());
+    }
+
+    /// edits of the action dropping `v`, last used by `&v` and dropped at the last `}`
+    fn drop_after(text: &str) -> Vec<lsp_types::TextEdit> {
+        let v = FnLocal::new(1, 1);
+        let mut func = function(vec![decl(
+            1,
+            "v",
+            range(loc(text, "v ="), loc(text, " = vec")),
+            range(loc(text, "v ="), loc(text, ");")),
+            vec![range(loc(text, ");"), Loc(text.rfind('}').unwrap() as u32))],
+        )]);
+        let dropped = Loc(text.rfind('}').unwrap() as u32);
+        func.basic_blocks.push(MirBasicBlock {
+            statements: Vec::new(),
+            terminator: Some(MirTerminator::Drop {
+                local: v,
+                range: range(dropped, dropped + 1),
+            }),
+        });
+        let borrow = decoration::Deco::ImmBorrow {
+            local: v,
+            place: MirPlace::new("v", Vec::new()),
+            range: range(loc(text, "&v"), loc(text, "&v") + 2),
+            hover_text: String::new(),
+            overlapped: false,
+        };
+        with_source(text, |source| {
+            edits(drop_after_last_use(
+                &[&func],
+                std::slice::from_ref(&borrow),
+                &[v],
+                source,
+            ))
+        })
+    }
+
+    #[test]
+    fn no_drop_inside_nested_blocks() {
+        let text = "fn f() {\n    let v = vec![1];\n    g(&v);\n    h();\n}\n";
+        let edits = drop_after(text);
+        assert_eq!(edits.len(), 1);
+        assert_eq!(edits[0].range.start, lsp_types::Position::new(2, 10));
+        assert_eq!(edits[0].new_text, "\n    drop(v);");
+
+        let text = "fn f() {\n    let v = vec![1];\n    for _ in 0..2 {\n        g(&v);\n    }\n    h();\n}\n";
+        assert!(drop_after(text).is_empty());
+
+        // braces in literals and comments do not open blocks
+        let text = "fn f() {\n    let v = vec![1];\n    let _ = ('{', \"{\", r#\"{\"#); // {\n    g(&v);\n    h();\n}\n";
+        assert_eq!(drop_after(text).len(), 1);
+    }
+
+    #[test]
+    fn crlf_line_breaks_are_kept() {
+        let text = "fn f() {\r\n    let v = vec![1];\r\n    g(&v);\r\n    h();\r\n}\r\n";
+        let dropped = drop_after(text);
+        assert_eq!(dropped.len(), 1);
+        assert_eq!(dropped[0].range.start, lsp_types::Position::new(2, 10));
+        assert_eq!(dropped[0].new_text, "\r\n    drop(v);");
+
+        let borrow = decoration::Deco::ImmBorrow {
+            local: FnLocal::new(1, 1),
+            place: MirPlace::new("v", Vec::new()),
+            range: range(loc(text, "&v"), loc(text, "&v") + 2),
+            hover_text: String::new(),
+            overlapped: false,
+        };
+        let wrapped = with_source(text, |source| {
+            edits(wrap_in_block(
+                &[&function(Vec::new())],
+                std::slice::from_ref(&borrow),
+                loc(text, "    g("),
+                loc(text, "    h("),
+                source,
+            ))
+        });
+        assert_eq!(wrapped.len(), 1);
+        assert_eq!(wrapped[0].new_text, "    {\r\n        g(&v);\r\n    }\r\n");
+    }
+}
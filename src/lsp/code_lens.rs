@@ -0,0 +1,156 @@
+use crate::{models::*, utils};
+use std::collections::HashSet;
+use tower_lsp::lsp_types;
+
+pub const OVERVIEW_COMMAND: &str = "rustowl.functionOverview";
+
+/// ownership statistics of user variables in a function
+struct Stats {
+    locals: HashSet<FnLocal>,
+    moves: usize,
+    borrows: usize,
+    clones: usize,
+    /// last use and drop-live ranges of variables with drop
+    guards: Vec<(Loc, Vec<Range>)>,
+    calls: Vec<Range>,
+}
+impl Stats {
+    fn new() -> Self {
+        Self {
+            locals: HashSet::new(),
+            moves: 0,
+            borrows: 0,
+            clones: 0,
+            guards: Vec::new(),
+            calls: Vec::new(),
+        }
+    }
+
+    fn collect(func: &Function) -> Self {
+        let mut stats = Self::new();
+        utils::mir_visit(func, &mut stats);
+        stats
+    }
+
+    /// variables kept alive only to be dropped while a function is called
+    fn held_across_call(&self) -> usize {
+        self.guards
+            .iter()
+            .filter(|(last_use, drop_range)| {
+                self.calls.iter().any(|call| {
+                    *last_use <= call.from()
+                        && drop_range
+                            .iter()
+                            .any(|v| v.from() <= call.from() && call.until() <= v.until())
+                })
+            })
+            .count()
+    }
+
+    /// e.g. `3 moves · 5 borrows · 2 clones · 1 guard held across call`
+    fn label(&self) -> String {
+        let plural =
+            |n: usize, one: &str, many: &str| format!("{n} {}", if n == 1 { one } else { many });
+        [
+            plural(self.moves, "move", "moves"),
+            plural(self.borrows, "borrow", "borrows"),
+            plural(self.clones, "clone", "clones"),
+            plural(
+                self.held_across_call(),
+                "guard held across call",
+                "guards held across calls",
+            ),
+        ]
+        .join(" · ")
+    }
+}
+impl utils::MirVisitor for Stats {
+    fn visit_decl(&mut self, decl: &MirDecl) {
+        if let MirDecl::User {
+            local,
+            lives,
+            drop,
+            drop_range,
+            ..
+        } = decl
+        {
+            self.locals.insert(*local);
+            if let (true, Some(last_use)) = (drop, lives.iter().map(|v| v.until()).max()) {
+                self.guards.push((last_use, drop_range.clone()));
+            }
+        }
+    }
+    fn visit_closure(&mut self, closure: &MirClosure) {
+        for capture in &closure.captures {
+            match capture.kind {
+                MirCaptureKind::Move => self.moves += 1,
+                MirCaptureKind::Ref | MirCaptureKind::MutRef => self.borrows += 1,
+                MirCaptureKind::Copy => {}
+            }
+        }
+    }
+    fn visit_stmt(&mut self, stmt: &MirStatement) {
+        if let MirStatement::Assign { rval, .. } = stmt {
+            match rval {
+                Some(MirRval::Move { target_local, .. }) if self.locals.contains(target_local) => {
+                    self.moves += 1
+                }
+                Some(MirRval::Borrow { target_local, .. })
+                    if self.locals.contains(target_local) =>
+                {
+                    self.borrows += 1
+                }
+                _ => {}
+            }
+        }
+    }
+    fn visit_term(&mut self, term: &MirTerminator) {
+        if let MirTerminator::Call { fn_span, clone, .. } = term {
+            if *clone {
+                self.clones += 1;
+            }
+            self.calls.push(*fn_span);
+        }
+    }
+}
+
+/// a lens with ownership statistics above each analyzed function with user variables,
+/// except closures
+pub fn code_lenses(
+    functions: &[&Function],
+    uri: &lsp_types::Url,
+    index: &utils::LineIndex,
+    encoding: utils::PositionEncoding,
+) -> Vec<lsp_types::CodeLens> {
+    let closures: HashSet<u64> = functions
+        .iter()
+        .flat_map(|v| &v.closures)
+        .map(|v| v.fn_id)
+        .collect();
+    let mut lenses = Vec::new();
+    for func in functions {
+        if closures.contains(&func.fn_id) {
+            continue;
+        }
+        let stats = Stats::collect(func);
+        // e.g. `main` generated for test targets
+        if stats.locals.is_empty() {
+            continue;
+        }
+        let position = index.lsp_position(func.span.from(), encoding);
+        let argument = serde_json::json!({
+            "position": position,
+            "document": { "uri": uri },
+        });
+        lenses.push(lsp_types::CodeLens {
+            range: lsp_types::Range::new(position, position),
+            command: Some(lsp_types::Command {
+                title: stats.label(),
+                command: OVERVIEW_COMMAND.to_owned(),
+                arguments: Some(vec![argument]),
+            }),
+            data: None,
+        });
+    }
+    lenses
+}
@@ -1,5 +1,9 @@
-use crate::{lsp::progress, models::*, utils};
-use std::collections::HashSet;
+use crate::{
+    lsp::{hover, progress},
+    models::*,
+    utils,
+};
+use std::collections::{HashMap, HashSet};
 use std::path::PathBuf;
 use tower_lsp::lsp_types;
 
@@ -14,18 +18,21 @@ pub enum Deco<R = Range> {
     },
     ImmBorrow {
         local: FnLocal,
+        place: MirPlace,
         range: R,
         hover_text: String,
         overlapped: bool,
     },
     MutBorrow {
         local: FnLocal,
+        place: MirPlace,
         range: R,
         hover_text: String,
         overlapped: bool,
     },
     Move {
         local: FnLocal,
+        place: MirPlace,
         range: R,
         hover_text: String,
         overlapped: bool,
@@ -48,175 +55,173 @@ pub enum Deco<R = Range> {
         hover_text: String,
         overlapped: bool,
     },
+    HeldAcrossAwait {
+        local: FnLocal,
+        range: R,
+        hover_text: String,
+        overlapped: bool,
+    },
 }
-impl Deco<Range> {
-    pub fn to_lsp_range(&self, s: &str) -> Deco<lsp_types::Range> {
+impl<R: Copy> Deco<R> {
+    pub fn local(&self) -> FnLocal {
+        match self {
+            Deco::Lifetime { local, .. }
+            | Deco::ImmBorrow { local, .. }
+            | Deco::MutBorrow { local, .. }
+            | Deco::Move { local, .. }
+            | Deco::Call { local, .. }
+            | Deco::SharedMut { local, .. }
+            | Deco::Outlive { local, .. }
+            | Deco::HeldAcrossAwait { local, .. } => *local,
+        }
+    }
+    pub fn range(&self) -> R {
+        match self {
+            Deco::Lifetime { range, .. }
+            | Deco::ImmBorrow { range, .. }
+            | Deco::MutBorrow { range, .. }
+            | Deco::Move { range, .. }
+            | Deco::Call { range, .. }
+            | Deco::SharedMut { range, .. }
+            | Deco::Outlive { range, .. }
+            | Deco::HeldAcrossAwait { range, .. } => *range,
+        }
+    }
+    pub fn overlapped(&self) -> bool {
+        match self {
+            Deco::Lifetime { overlapped, .. }
+            | Deco::ImmBorrow { overlapped, .. }
+            | Deco::MutBorrow { overlapped, .. }
+            | Deco::Move { overlapped, .. }
+            | Deco::Call { overlapped, .. }
+            | Deco::SharedMut { overlapped, .. }
+            | Deco::Outlive { overlapped, .. }
+            | Deco::HeldAcrossAwait { overlapped, .. } => *overlapped,
+        }
+    }
+    /// the same decoration drawn at another range
+    pub fn with_range<S>(&self, range: S, overlapped: bool) -> Deco<S> {
         match self.clone() {
             Deco::Lifetime {
+                local, hover_text, ..
+            } => Deco::Lifetime {
                 local,
                 range,
                 hover_text,
                 overlapped,
-            } => {
-                let start = utils::index_to_line_char(s, range.from());
-                let end = utils::index_to_line_char(s, range.until());
-                let start = lsp_types::Position {
-                    line: start.0,
-                    character: start.1,
-                };
-                let end = lsp_types::Position {
-                    line: end.0,
-                    character: end.1,
-                };
-                Deco::Lifetime {
-                    local,
-                    range: lsp_types::Range { start, end },
-                    hover_text,
-                    overlapped,
-                }
-            }
+            },
             Deco::ImmBorrow {
                 local,
+                place,
+                hover_text,
+                ..
+            } => Deco::ImmBorrow {
+                local,
+                place,
                 range,
                 hover_text,
                 overlapped,
-            } => {
-                let start = utils::index_to_line_char(s, range.from());
-                let end = utils::index_to_line_char(s, range.until());
-                let start = lsp_types::Position {
-                    line: start.0,
-                    character: start.1,
-                };
-                let end = lsp_types::Position {
-                    line: end.0,
-                    character: end.1,
-                };
-                Deco::ImmBorrow {
-                    local,
-                    range: lsp_types::Range { start, end },
-                    hover_text,
-                    overlapped,
-                }
-            }
+            },
             Deco::MutBorrow {
                 local,
+                place,
+                hover_text,
+                ..
+            } => Deco::MutBorrow {
+                local,
+                place,
                 range,
                 hover_text,
                 overlapped,
-            } => {
-                let start = utils::index_to_line_char(s, range.from());
-                let end = utils::index_to_line_char(s, range.until());
-                let start = lsp_types::Position {
-                    line: start.0,
-                    character: start.1,
-                };
-                let end = lsp_types::Position {
-                    line: end.0,
-                    character: end.1,
-                };
-                Deco::MutBorrow {
-                    local,
-                    range: lsp_types::Range { start, end },
-                    hover_text,
-                    overlapped,
-                }
-            }
+            },
             Deco::Move {
                 local,
+                place,
+                hover_text,
+                ..
+            } => Deco::Move {
+                local,
+                place,
                 range,
                 hover_text,
                 overlapped,
-            } => {
-                let start = utils::index_to_line_char(s, range.from());
-                let end = utils::index_to_line_char(s, range.until());
-                let start = lsp_types::Position {
-                    line: start.0,
-                    character: start.1,
-                };
-                let end = lsp_types::Position {
-                    line: end.0,
-                    character: end.1,
-                };
-                Deco::Move {
-                    local,
-                    range: lsp_types::Range { start, end },
-                    hover_text,
-                    overlapped,
-                }
-            }
+            },
             Deco::Call {
+                local, hover_text, ..
+            } => Deco::Call {
                 local,
                 range,
                 hover_text,
                 overlapped,
-            } => {
-                let start = utils::index_to_line_char(s, range.from());
-                let end = utils::index_to_line_char(s, range.until());
-                let start = lsp_types::Position {
-                    line: start.0,
-                    character: start.1,
-                };
-                let end = lsp_types::Position {
-                    line: end.0,
-                    character: end.1,
-                };
-                Deco::Call {
-                    local,
-                    range: lsp_types::Range { start, end },
-                    hover_text,
-                    overlapped,
-                }
-            }
+            },
             Deco::SharedMut {
+                local, hover_text, ..
+            } => Deco::SharedMut {
                 local,
                 range,
                 hover_text,
                 overlapped,
-            } => {
-                let start = utils::index_to_line_char(s, range.from());
-                let end = utils::index_to_line_char(s, range.until());
-                let start = lsp_types::Position {
-                    line: start.0,
-                    character: start.1,
-                };
-                let end = lsp_types::Position {
-                    line: end.0,
-                    character: end.1,
-                };
-                Deco::SharedMut {
-                    local,
-                    range: lsp_types::Range { start, end },
-                    hover_text,
-                    overlapped,
-                }
-            }
-
+            },
             Deco::Outlive {
+                local, hover_text, ..
+            } => Deco::Outlive {
                 local,
                 range,
                 hover_text,
                 overlapped,
-            } => {
-                let start = utils::index_to_line_char(s, range.from());
-                let end = utils::index_to_line_char(s, range.until());
-                let start = lsp_types::Position {
-                    line: start.0,
-                    character: start.1,
-                };
-                let end = lsp_types::Position {
-                    line: end.0,
-                    character: end.1,
-                };
-                Deco::Outlive {
-                    local,
-                    range: lsp_types::Range { start, end },
-                    hover_text,
-                    overlapped,
-                }
-            }
+            },
+            Deco::HeldAcrossAwait {
+                local, hover_text, ..
+            } => Deco::HeldAcrossAwait {
+                local,
+                range,
+                hover_text,
+                overlapped,
+            },
         }
     }
 }
+impl Deco<Range> {
+    pub fn to_lsp_range(
+        &self,
+        index: &utils::LineIndex,
+        encoding: utils::PositionEncoding,
+    ) -> Deco<lsp_types::Range> {
+        self.with_range(index.lsp_range(self.range(), encoding), self.overlapped())
+    }
+}
+impl Deco<lsp_types::Range> {
+    /// highlight for generic clients, `None` for hidden or unmapped decorations
+    pub fn to_highlight(&self) -> Option<lsp_types::DocumentHighlight> {
+        let (range, kind) = match self {
+            Deco::Lifetime {
+                range,
+                overlapped: false,
+                ..
+            } => (range, lsp_types::DocumentHighlightKind::TEXT),
+            Deco::ImmBorrow {
+                range,
+                overlapped: false,
+                ..
+            } => (range, lsp_types::DocumentHighlightKind::READ),
+            Deco::MutBorrow {
+                range,
+                overlapped: false,
+                ..
+            }
+            | Deco::Move {
+                range,
+                overlapped: false,
+                ..
+            } => (range, lsp_types::DocumentHighlightKind::WRITE),
+            _ => return None,
+        };
+        Some(lsp_types::DocumentHighlight {
+            range: *range,
+            kind: Some(kind),
+        })
+    }
+}
 #[derive(serde::Serialize, Clone, Debug)]
 pub struct Decorations {
     pub is_analyzed: bool,
@@ -240,23 +245,101 @@ impl CursorRequest {
     }
 }
 
+#[derive(serde::Deserialize, Clone, Debug)]
+#[serde(rename_all = "snake_case")]
+pub struct FileDecorationsRequest {
+    pub document: lsp_types::TextDocumentIdentifier,
+    /// `etag` of the last response, to skip unchanged decorations
+    pub etag: Option<String>,
+}
+impl FileDecorationsRequest {
+    pub fn path(&self) -> Option<PathBuf> {
+        self.document.uri.to_file_path().ok()
+    }
+}
+
+#[derive(serde::Serialize, Clone, Debug)]
+pub struct LocalDecorations {
+    pub local: FnLocal,
+    /// ranges where the cursor selects the local
+    pub selectable: Vec<lsp_types::Range>,
+    pub decorations: Vec<Deco<lsp_types::Range>>,
+}
+
+#[derive(serde::Serialize, Clone, Debug)]
+pub struct FileDecorations {
+    pub is_analyzed: bool,
+    pub status: progress::AnalysisStatus,
+    pub path: Option<PathBuf>,
+    pub etag: Option<String>,
+    /// `None` if unchanged since the request `etag`
+    pub locals: Option<Vec<LocalDecorations>>,
+}
+
+/// decorations of every selectable local in up-to-date functions of the file,
+/// with the ranges where it is selected
+pub fn file_decorations(file: &File) -> Vec<(FnLocal, Vec<Range>, Vec<Deco>)> {
+    let functions: Vec<_> = file.functions().collect();
+    let mut candidates = Candidates::default();
+    for func in &functions {
+        utils::mir_visit(func, &mut candidates);
+    }
+    let mut selectable: HashMap<FnLocal, Vec<Range>> = HashMap::new();
+    for (_, local, range) in candidates.0 {
+        selectable.entry(local).or_default().push(range);
+    }
+
+    let mut locals: Vec<_> = selectable.into_iter().collect();
+    locals.sort_by_key(|(local, _)| (local.fn_id, local.id));
+    locals
+        .into_iter()
+        .map(|(local, mut ranges)| {
+            // e.g. a variable moved and borrowed by the same expression
+            ranges.sort_by_key(|v| (v.from(), v.until()));
+            ranges.dedup();
+            // decorations of a local come from the function it belongs to
+            let mut calc = CalcDecos::new([local]);
+            for func in functions.iter().filter(|v| v.fn_id == local.fn_id) {
+                utils::mir_visit(func, &mut calc);
+            }
+            calc.handle_overlapping();
+            (local, ranges, calc.decorations())
+        })
+        .collect()
+}
+
 #[derive(Clone, Copy, Debug)]
 enum SelectReason {
     Var,
     Move,
     Borrow,
     Call,
+    /// held across a suspension point
+    Held,
 }
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Debug)]
 pub struct SelectLocal {
     pos: Loc,
     selected: Option<(SelectReason, FnLocal, Range)>,
+    held: Option<(Range, Vec<FnLocal>)>,
 }
 impl SelectLocal {
     pub fn new(pos: Loc) -> Self {
         Self {
             pos,
             selected: None,
+            held: None,
+        }
+    }
+
+    /// select every local held across the suspension point under the cursor
+    fn select_held(&mut self, local: FnLocal, range: Range) {
+        if range.from() <= self.pos && self.pos <= range.until() {
+            match &mut self.held {
+                Some((old_range, locals)) if *old_range == range => locals.push(local),
+                Some((old_range, _)) if old_range.size() <= range.size() => {}
+                _ => self.held = Some((range, vec![local])),
+            }
         }
     }
 
@@ -289,14 +372,65 @@ impl SelectLocal {
         }
     }
 
-    pub fn selected(&self) -> Option<FnLocal> {
-        self.selected.map(|v| v.1)
+    fn select_all(&mut self, candidates: Candidates) {
+        for (reason, local, range) in candidates.0 {
+            match reason {
+                SelectReason::Held => self.select_held(local, range),
+                _ => self.select(reason, local, range),
+            }
+        }
+    }
+
+    pub fn selected(&self) -> Vec<FnLocal> {
+        match (&self.held, self.selected) {
+            (Some((held_range, locals)), Some((_, _, range)))
+                if held_range.size() <= range.size() =>
+            {
+                locals.clone()
+            }
+            (Some((_, locals)), None) => locals.clone(),
+            (_, selected) => selected.map(|v| v.1).into_iter().collect(),
+        }
     }
 }
 impl utils::MirVisitor for SelectLocal {
+    fn visit_decl(&mut self, decl: &MirDecl) {
+        let mut candidates = Candidates::default();
+        candidates.visit_decl(decl);
+        self.select_all(candidates);
+    }
+    fn visit_stmt(&mut self, stmt: &MirStatement) {
+        let mut candidates = Candidates::default();
+        candidates.visit_stmt(stmt);
+        self.select_all(candidates);
+    }
+    fn visit_term(&mut self, term: &MirTerminator) {
+        let mut candidates = Candidates::default();
+        candidates.visit_term(term);
+        self.select_all(candidates);
+    }
+}
+
+/// ranges where the cursor selects each local
+#[derive(Default, Clone, Debug)]
+struct Candidates(Vec<(SelectReason, FnLocal, Range)>);
+impl utils::MirVisitor for Candidates {
     fn visit_decl(&mut self, decl: &MirDecl) {
         if let MirDecl::User { local, span, .. } = decl {
-            self.select(SelectReason::Var, *local, *span);
+            self.0.push((SelectReason::Var, *local, *span));
+        }
+        let (MirDecl::User {
+            local,
+            held_across_await,
+            ..
+        }
+        | MirDecl::Other {
+            local,
+            held_across_await,
+            ..
+        }) = decl;
+        for range in held_across_await {
+            self.0.push((SelectReason::Held, *local, *range));
         }
     }
     fn visit_stmt(&mut self, stmt: &MirStatement) {
@@ -305,15 +439,16 @@ impl utils::MirVisitor for SelectLocal {
                 Some(MirRval::Move {
                     target_local,
                     range,
+                    ..
                 }) => {
-                    self.select(SelectReason::Move, *target_local, *range);
+                    self.0.push((SelectReason::Move, *target_local, *range));
                 }
                 Some(MirRval::Borrow {
                     target_local,
                     range,
                     ..
                 }) => {
-                    self.select(SelectReason::Borrow, *target_local, *range);
+                    self.0.push((SelectReason::Borrow, *target_local, *range));
                 }
                 _ => {}
             }
@@ -323,17 +458,20 @@ impl utils::MirVisitor for SelectLocal {
         if let MirTerminator::Call {
             destination_local,
             fn_span,
+            ..
         } = term
         {
-            self.select(SelectReason::Call, *destination_local, *fn_span);
+            self.0
+                .push((SelectReason::Call, *destination_local, *fn_span));
         }
     }
 }
+
 #[derive(Clone, Debug)]
 pub struct CalcDecos {
     locals: HashSet<FnLocal>,
     decorations: Vec<Deco>,
-    current_fn_id: u32,
+    current_fn_id: u64,
 }
 impl CalcDecos {
     pub fn new(locals: impl IntoIterator<Item = FnLocal>) -> Self {
@@ -353,6 +491,7 @@ impl CalcDecos {
             Deco::Call { .. } => 4,
             Deco::SharedMut { .. } => 5,
             Deco::Outlive { .. } => 6,
+            Deco::HeldAcrossAwait { .. } => 7,
         }
     }
 
@@ -360,157 +499,35 @@ impl CalcDecos {
         self.decorations.sort_by_key(Self::get_deco_order);
     }
 
+    /// split decorations so that parts covered by decorations of later order are marked
+    /// as overlapped, leaving out the location next to each overlapped part
     pub fn handle_overlapping(&mut self) {
         self.sort_by_definition();
-        let mut i = 1;
-        'outer: while i < self.decorations.len() {
-            let current_range = match &self.decorations[i] {
-                Deco::Lifetime { range, .. }
-                | Deco::ImmBorrow { range, .. }
-                | Deco::MutBorrow { range, .. }
-                | Deco::Move { range, .. }
-                | Deco::Call { range, .. }
-                | Deco::SharedMut { range, .. }
-                | Deco::Outlive { range, .. } => *range,
-            };
-
-            let mut j = 0;
-            while j < i {
-                let prev = &self.decorations[j];
-                if prev == &self.decorations[i] {
-                    self.decorations.remove(i);
-                    continue 'outer;
-                }
-                let (prev_range, prev_overlapped) = match prev {
-                    Deco::Lifetime {
-                        range, overlapped, ..
-                    }
-                    | Deco::ImmBorrow {
-                        range, overlapped, ..
-                    }
-                    | Deco::MutBorrow {
-                        range, overlapped, ..
-                    }
-                    | Deco::Move {
-                        range, overlapped, ..
-                    }
-                    | Deco::Call {
-                        range, overlapped, ..
-                    }
-                    | Deco::SharedMut {
-                        range, overlapped, ..
-                    }
-                    | Deco::Outlive {
-                        range, overlapped, ..
-                    } => (*range, *overlapped),
-                };
-
-                if prev_overlapped {
-                    j += 1;
-                    continue;
-                }
-
-                if let Some(common) = utils::common_range(current_range, prev_range) {
-                    let mut new_decos = Vec::new();
-                    let non_overlapping = utils::exclude_ranges(vec![prev_range], vec![common]);
-
-                    for range in non_overlapping {
-                        let new_deco = match prev {
-                            Deco::Lifetime {
-                                local, hover_text, ..
-                            } => Deco::Lifetime {
-                                local: *local,
-                                range,
-                                hover_text: hover_text.clone(),
-                                overlapped: false,
-                            },
-                            Deco::ImmBorrow {
-                                local, hover_text, ..
-                            } => Deco::ImmBorrow {
-                                local: *local,
-                                range,
-                                hover_text: hover_text.clone(),
-                                overlapped: false,
-                            },
-                            Deco::MutBorrow {
-                                local, hover_text, ..
-                            } => Deco::MutBorrow {
-                                local: *local,
-                                range,
-                                hover_text: hover_text.clone(),
-                                overlapped: false,
-                            },
-                            Deco::Move {
-                                local, hover_text, ..
-                            } => Deco::Move {
-                                local: *local,
-                                range,
-                                hover_text: hover_text.clone(),
-                                overlapped: false,
-                            },
-                            Deco::Call {
-                                local, hover_text, ..
-                            } => Deco::Call {
-                                local: *local,
-                                range,
-                                hover_text: hover_text.clone(),
-                                overlapped: false,
-                            },
-                            Deco::SharedMut {
-                                local, hover_text, ..
-                            } => Deco::SharedMut {
-                                local: *local,
-                                range,
-                                hover_text: hover_text.clone(),
-                                overlapped: false,
-                            },
-                            Deco::Outlive {
-                                local, hover_text, ..
-                            } => Deco::Outlive {
-                                local: *local,
-                                range,
-                                hover_text: hover_text.clone(),
-                                overlapped: false,
-                            },
-                        };
-                        new_decos.push(new_deco);
-                    }
-
-                    match &mut self.decorations[j] {
-                        Deco::Lifetime {
-                            range, overlapped, ..
-                        }
-                        | Deco::ImmBorrow {
-                            range, overlapped, ..
-                        }
-                        | Deco::MutBorrow {
-                            range, overlapped, ..
-                        }
-                        | Deco::Move {
-                            range, overlapped, ..
-                        }
-                        | Deco::Call {
-                            range, overlapped, ..
-                        }
-                        | Deco::SharedMut {
-                            range, overlapped, ..
-                        }
-                        | Deco::Outlive {
-                            range, overlapped, ..
-                        } => {
-                            *range = common;
-                            *overlapped = true;
-                        }
-                    }
+        // identical decorations are drawn once
+        let mut unique: Vec<Deco> = Vec::with_capacity(self.decorations.len());
+        for deco in std::mem::take(&mut self.decorations) {
+            if !unique.contains(&deco) {
+                unique.push(deco);
+            }
+        }
 
-                    for (jj, deco) in new_decos.into_iter().enumerate() {
-                        self.decorations.insert(j + jj + 1, deco);
-                    }
-                }
-                j += 1;
+        let mut covered = RangeSet::new();
+        let mut decorations = Vec::with_capacity(self.decorations.len());
+        for deco in unique.into_iter().rev() {
+            let range = deco.range();
+            let overlapped = covered.clip(range);
+            let visible = RangeSet::from(vec![range]).exclude(&overlapped);
+            // pushed in reverse, as the whole list is reversed afterwards
+            for range in visible.into_vec().into_iter().rev() {
+                decorations.push(deco.with_range(range, false));
+            }
+            for range in overlapped.into_vec().into_iter().rev() {
+                decorations.push(deco.with_range(range, true));
             }
-            i += 1;
+            covered.insert(range);
         }
+        decorations.reverse();
+        self.decorations = decorations;
     }
 
     pub fn decorations(self) -> Vec<Deco> {
@@ -519,54 +536,62 @@ impl CalcDecos {
 }
 impl utils::MirVisitor for CalcDecos {
     fn visit_decl(&mut self, decl: &MirDecl) {
-        let (local, lives, shared_borrow, mutable_borrow, drop_range, must_live_at, name) =
+        let (local, ty, lives, place_borrows, drop_range, must_live_at, held_across_await, name) =
             match decl {
                 MirDecl::User {
                     local,
                     name,
+                    ty,
                     lives,
-                    shared_borrow,
-                    mutable_borrow,
+                    place_borrows,
                     drop_range,
                     must_live_at,
+                    held_across_await,
                     ..
                 } => (
                     *local,
+                    ty,
                     lives,
-                    shared_borrow,
-                    mutable_borrow,
+                    place_borrows,
                     drop_range,
                     must_live_at,
+                    held_across_await,
                     Some(name),
                 ),
                 MirDecl::Other {
                     local,
+                    ty,
                     lives,
-                    shared_borrow,
-                    mutable_borrow,
+                    place_borrows,
                     drop_range,
                     must_live_at,
+                    held_across_await,
                     ..
                 } => (
                     *local,
+                    ty,
                     lives,
-                    shared_borrow,
-                    mutable_borrow,
+                    place_borrows,
                     drop_range,
                     must_live_at,
+                    held_across_await,
                     None,
                 ),
             };
+        let (MirDecl::User {
+            must_live_blame, ..
+        }
+        | MirDecl::Other {
+            must_live_blame, ..
+        }) = decl;
         self.current_fn_id = local.fn_id;
         if self.locals.contains(&local) {
             let var_str = name
                 .map(|v| format!("variable `{v}`"))
                 .unwrap_or("anonymous variable".to_owned());
             // merge Drop object lives
-            let mut drop_copy_live = lives.clone();
-            drop_copy_live.extend_from_slice(drop_range);
-            drop_copy_live = utils::eliminated_ranges(drop_copy_live.clone());
-            for range in &drop_copy_live {
+            let drop_copy_live: RangeSet = lives.iter().chain(drop_range).copied().collect();
+            for range in drop_copy_live.ranges() {
                 self.decorations.push(Deco::Lifetime {
                     local,
                     range: *range,
@@ -574,26 +599,134 @@ impl utils::MirVisitor for CalcDecos {
                     overlapped: false,
                 });
             }
-            let mut borrow_ranges = shared_borrow.clone();
-            borrow_ranges.extend_from_slice(mutable_borrow);
-            let shared_mut = utils::common_ranges(&borrow_ranges);
-            for range in shared_mut {
-                self.decorations.push(Deco::SharedMut {
+            // borrows of disjoint fields never conflict
+            for shared in place_borrows {
+                for mutable in place_borrows {
+                    if !shared.place.overlaps(&mutable.place) {
+                        continue;
+                    }
+                    let place = if shared.place.projection.len() < mutable.place.projection.len() {
+                        &mutable.place
+                    } else {
+                        &shared.place
+                    };
+                    let place_str = if place.is_whole() {
+                        var_str.clone()
+                    } else {
+                        format!("`{}`", place.path)
+                    };
+                    let mut borrow_ranges = shared.shared_borrow.clone();
+                    borrow_ranges.extend_from_slice(&mutable.mutable_borrow);
+                    for range in RangeSet::overlaps(&borrow_ranges).into_vec() {
+                        self.decorations.push(Deco::SharedMut {
+                            local,
+                            range,
+                            hover_text: format!(
+                                "immutable and mutable borrows of {place_str} exist here"
+                            ),
+                            overlapped: false,
+                        });
+                    }
+                }
+            }
+            let outlive = RangeSet::from(must_live_at.clone()).exclude(&drop_copy_live);
+            for range in outlive.into_vec() {
+                let blame = must_live_blame
+                    .iter()
+                    .find(|v| v.range.from() < range.until() && range.from() < v.range.until());
+                let hover_text = match blame {
+                    Some(blame) => format!(
+                        "{var_str} is required to live here: {}",
+                        hover::explain(blame, None)
+                    ),
+                    None => format!("{var_str} is required to live here"),
+                };
+                self.decorations.push(Deco::Outlive {
                     local,
                     range,
-                    hover_text: format!("immutable and mutable borrows of {var_str} exist here"),
+                    hover_text,
                     overlapped: false,
                 });
             }
-            let outlive = utils::exclude_ranges(must_live_at.clone(), drop_copy_live);
-            for range in outlive {
-                self.decorations.push(Deco::Outlive {
+            for range in held_across_await {
+                let hover_text =
+                    format!("{var_str} of type `{ty}` is held across this suspension point");
+                // several locals are usually held across the same `.await`
+                let existing = self.decorations.iter_mut().find_map(|deco| match deco {
+                    Deco::HeldAcrossAwait {
+                        range: existing_range,
+                        hover_text,
+                        ..
+                    } if existing_range == range => Some(hover_text),
+                    _ => None,
+                });
+                if let Some(existing) = existing {
+                    if !existing.lines().any(|line| line == hover_text) {
+                        existing.push('\n');
+                        existing.push_str(&hover_text);
+                    }
+                } else {
+                    self.decorations.push(Deco::HeldAcrossAwait {
+                        local,
+                        range: *range,
+                        hover_text,
+                        overlapped: false,
+                    });
+                }
+            }
+        }
+    }
+    fn visit_closure(&mut self, closure: &MirClosure) {
+        let closure_selected = self.locals.contains(&closure.local);
+        let closure_str = match closure.kind {
+            MirClosureKind::Fn => "`Fn`",
+            MirClosureKind::FnMut => "`FnMut`",
+            MirClosureKind::FnOnce => "`FnOnce`",
+        };
+        for capture in &closure.captures {
+            if !closure_selected && !self.locals.contains(&capture.local) {
+                continue;
+            }
+            let local = capture.local;
+            let place = capture.place.clone();
+            let range = capture.range;
+            let hover_text = |by: &str| {
+                format!(
+                    "`{}` captured by {by} in {closure_str} closure",
+                    capture.place.path
+                )
+            };
+            self.decorations.push(match capture.kind {
+                MirCaptureKind::Ref => Deco::ImmBorrow {
                     local,
+                    place,
                     range,
-                    hover_text: format!("{var_str} is required to live here"),
+                    hover_text: hover_text("reference"),
                     overlapped: false,
-                });
-            }
+                },
+                MirCaptureKind::MutRef => Deco::MutBorrow {
+                    local,
+                    place,
+                    range,
+                    hover_text: hover_text("mutable reference"),
+                    overlapped: false,
+                },
+                MirCaptureKind::Move => Deco::Move {
+                    local,
+                    place,
+                    range,
+                    hover_text: hover_text("move"),
+                    overlapped: false,
+                },
+                // copying leaves the variable usable, as a shared borrow does
+                MirCaptureKind::Copy => Deco::ImmBorrow {
+                    local,
+                    place,
+                    range,
+                    hover_text: hover_text("copy"),
+                    overlapped: false,
+                },
+            });
         }
     }
     fn visit_stmt(&mut self, stmt: &MirStatement) {
@@ -601,36 +734,51 @@ impl utils::MirVisitor for CalcDecos {
             match rval {
                 Some(MirRval::Move {
                     target_local,
+                    target_place,
                     range,
                 }) => {
                     if self.locals.contains(target_local) {
+                        let hover_text = if target_place.is_whole() {
+                            "variable moved".to_string()
+                        } else {
+                            format!("`{}` moved (partial move)", target_place.path)
+                        };
                         self.decorations.push(Deco::Move {
                             local: *target_local,
+                            place: target_place.clone(),
                             range: *range,
-                            hover_text: "variable moved".to_string(),
+                            hover_text,
                             overlapped: false,
                         });
                     }
                 }
                 Some(MirRval::Borrow {
                     target_local,
+                    target_place,
                     range,
                     mutable,
                     ..
                 }) => {
                     if self.locals.contains(target_local) {
+                        let place_str = if target_place.is_whole() {
+                            String::new()
+                        } else {
+                            format!(" of `{}`", target_place.path)
+                        };
                         if *mutable {
                             self.decorations.push(Deco::MutBorrow {
                                 local: *target_local,
+                                place: target_place.clone(),
                                 range: *range,
-                                hover_text: "mutable borrow".to_string(),
+                                hover_text: format!("mutable borrow{place_str}"),
                                 overlapped: false,
                             });
                         } else {
                             self.decorations.push(Deco::ImmBorrow {
                                 local: *target_local,
+                                place: target_place.clone(),
                                 range: *range,
-                                hover_text: "immutable borrow".to_string(),
+                                hover_text: format!("immutable borrow{place_str}"),
                                 overlapped: false,
                             });
                         }
@@ -644,6 +792,7 @@ impl utils::MirVisitor for CalcDecos {
         if let MirTerminator::Call {
             destination_local,
             fn_span,
+            ..
         } = term
         {
             if self.locals.contains(destination_local) {
@@ -678,3 +827,77 @@ impl utils::MirVisitor for CalcDecos {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{range, user_decl};
+
+    /// `fn f() { let x = ..; }` at `span`, with `x` declared at its start
+    fn function(fn_id: u64, span: Range) -> Function {
+        let from = span.from().0;
+        Function {
+            decls: vec![user_decl(
+                FnLocal::new(1, fn_id),
+                "x",
+                "String",
+                range(from + 1, from + 2),
+                vec![range(from + 1, from + 5)],
+                vec![range(from + 5, from + 8)],
+            )],
+            ..crate::testing::function(fn_id, "krate", &format!("krate::f{fn_id}"), span)
+        }
+    }
+
+    #[test]
+    fn edited_functions_are_not_decorated() {
+        let mut file = File::new(vec![function(1, range(0, 10)), function(2, range(20, 30))]);
+        let fn_ids = |file: &File| -> Vec<u64> {
+            file_decorations(file)
+                .into_iter()
+                .filter(|(_, _, decorations)| !decorations.is_empty())
+                .map(|(local, _, _)| local.fn_id)
+                .collect()
+        };
+        assert_eq!(fn_ids(&file), [1, 2]);
+
+        // typing inside the second function
+        file.edit(Loc(25), Loc(25), 3);
+        assert_eq!(fn_ids(&file), [1]);
+        assert_eq!(file.functions_at(Loc(26)).count(), 0);
+
+        // the first function is shifted by edits before it, but not stale
+        file.edit(Loc(0), Loc(0), 2);
+        assert_eq!(fn_ids(&file), [1]);
+        let (_, selectable, _) = &file_decorations(&file)[0];
+        assert_eq!(selectable, &[range(3, 4)]);
+    }
+
+    #[test]
+    fn copied_captures_are_not_moves() {
+        let mut func = function(1, range(0, 10));
+        func.closures.push(MirClosure {
+            local: FnLocal::new(2, 1),
+            fn_id: 3,
+            kind: MirClosureKind::Fn,
+            range: range(6, 9),
+            captures: vec![MirCapture {
+                local: FnLocal::new(1, 1),
+                place: MirPlace::new("x", Vec::new()),
+                kind: MirCaptureKind::Copy,
+                range: range(7, 8),
+            }],
+        });
+        let mut calc = CalcDecos::new([FnLocal::new(1, 1)]);
+        utils::mir_visit(&func, &mut calc);
+        let captures: Vec<_> = calc
+            .decorations()
+            .into_iter()
+            .filter(|v| !matches!(v, Deco::Lifetime { .. }))
+            .collect();
+        assert!(matches!(
+            &captures[..],
+            [Deco::ImmBorrow { range: r, .. }] if *r == range(7, 8)
+        ));
+    }
+}
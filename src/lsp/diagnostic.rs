@@ -0,0 +1,130 @@
+use crate::{lsp::document, models::*, utils};
+use tower_lsp::{Client, lsp_types};
+
+fn diagnostic(
+    range: lsp_types::Range,
+    message: String,
+    related: Vec<lsp_types::DiagnosticRelatedInformation>,
+) -> lsp_types::Diagnostic {
+    lsp_types::Diagnostic {
+        range,
+        severity: Some(lsp_types::DiagnosticSeverity::ERROR),
+        source: Some("rustowl".to_owned()),
+        message,
+        related_information: Some(related),
+        ..Default::default()
+    }
+}
+
+/// e.g. `` `'a` `` or `` the lifetime of `x` ``
+fn describe(region: &MirRegion) -> String {
+    match region {
+        MirRegion::Variable { name, .. } => format!("the lifetime of `{name}`"),
+        MirRegion::Return { name: Some(name) } | MirRegion::Universal { name: Some(name) } => {
+            format!("`{name}`")
+        }
+        MirRegion::Return { name: None } => "the returned lifetime".to_owned(),
+        MirRegion::Universal { name: None } => "a lifetime of the signature".to_owned(),
+        MirRegion::Anonymous => "an anonymous lifetime".to_owned(),
+    }
+}
+
+/// convert borrow check errors of a function into LSP diagnostics
+pub fn borrow_errors(
+    func: &Function,
+    index: &utils::LineIndex,
+    uri: &lsp_types::Url,
+    encoding: utils::PositionEncoding,
+) -> Vec<lsp_types::Diagnostic> {
+    let location = |range: Range| lsp_types::Location {
+        uri: uri.clone(),
+        range: index.lsp_range(range, encoding),
+    };
+    func.errors
+        .iter()
+        .map(|error| match error {
+            MirBorrowError::Loan {
+                place,
+                mutable,
+                borrowed,
+                invalidated,
+                ..
+            } => {
+                let kind = if *mutable { "mutable" } else { "immutable" };
+                diagnostic(
+                    index.lsp_range(*invalidated, encoding),
+                    format!(
+                        "{kind} borrow of `{}` is invalidated here while it is still in use",
+                        place.path
+                    ),
+                    vec![lsp_types::DiagnosticRelatedInformation {
+                        location: location(*borrowed),
+                        message: format!("{kind} borrow of `{}` created here", place.path),
+                    }],
+                )
+            }
+            MirBorrowError::Subset {
+                range,
+                longer,
+                shorter,
+                points,
+            } => {
+                let relation = format!("{} must outlive {}", describe(longer), describe(shorter));
+                diagnostic(
+                    index.lsp_range(*range, encoding),
+                    format!("lifetime may not live long enough: {relation}"),
+                    points
+                        .iter()
+                        .map(|range| lsp_types::DiagnosticRelatedInformation {
+                            location: location(*range),
+                            message: format!("{relation} here as well"),
+                        })
+                        .collect(),
+                )
+            }
+            MirBorrowError::Move {
+                place,
+                moved,
+                accessed,
+                ..
+            } => diagnostic(
+                index.lsp_range(*accessed, encoding),
+                format!("use of moved value `{}`", place.path),
+                moved
+                    .iter()
+                    .map(|range| lsp_types::DiagnosticRelatedInformation {
+                        location: location(*range),
+                        message: format!("`{}` moved here", place.path),
+                    })
+                    .collect(),
+            ),
+        })
+        .collect()
+}
+
+/// publish borrow check errors of every analyzed file
+pub async fn publish(
+    client: &Client,
+    analyzed: &Crate,
+    documents: &document::Documents,
+    encoding: utils::PositionEncoding,
+) {
+    for (filename, file) in analyzed.0.iter() {
+        let Ok(uri) = lsp_types::Url::from_file_path(filename) else {
+            continue;
+        };
+        let Some(text) = document::read(documents, filename).await else {
+            continue;
+        };
+        let index = utils::LineIndex::new(&text);
+        let mut diagnostics = Vec::new();
+        for item in file.functions() {
+            for diagnostic in borrow_errors(item, &index, &uri, encoding) {
+                if !diagnostics.contains(&diagnostic) {
+                    diagnostics.push(diagnostic);
+                }
+            }
+        }
+        client.publish_diagnostics(uri, diagnostics, None).await;
+    }
+}
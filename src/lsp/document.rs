@@ -0,0 +1,162 @@
+use crate::{
+    models::Loc,
+    utils::{LineIndex, PositionEncoding},
+};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use tower_lsp::lsp_types;
+
+/// text of a document opened in the editor, which may not be saved yet
+#[derive(Clone, Debug)]
+pub struct Document {
+    pub text: String,
+    pub version: i32,
+}
+
+pub type Documents = HashMap<PathBuf, Document>;
+
+/// replacement of `from..until` with `len` characters, in character indices
+#[derive(Clone, Copy, Debug)]
+pub struct Edit {
+    pub from: Loc,
+    pub until: Loc,
+    pub len: u32,
+}
+
+impl Document {
+    pub fn new(text: String, version: i32) -> Self {
+        Self { text, version }
+    }
+
+    /// applies changes in order and returns them as edits of the text
+    pub fn apply(
+        &mut self,
+        changes: Vec<lsp_types::TextDocumentContentChangeEvent>,
+        version: i32,
+        encoding: PositionEncoding,
+    ) -> Vec<Edit> {
+        let mut edits = Vec::new();
+        for change in changes {
+            let index = LineIndex::new(&self.text);
+            let (from, until) = if let Some(range) = change.range {
+                // positions beyond the line end are clamped before its line break
+                let loc = |position: lsp_types::Position| {
+                    let loc = index.index(position.line, position.character, encoding);
+                    let byte = index.byte(loc) as usize;
+                    if self.text[..byte].ends_with('\r') && self.text[byte..].starts_with('\n') {
+                        loc - 1
+                    } else {
+                        loc
+                    }
+                };
+                let from = loc(range.start);
+                (from, loc(range.end).max(from))
+            } else {
+                (Loc(0), Loc(index.len()))
+            };
+            edits.push(Edit {
+                from,
+                until,
+                len: change.text.chars().count() as u32,
+            });
+            let (start, end) = (index.byte(from) as usize, index.byte(until) as usize);
+            self.text.replace_range(start..end, &change.text);
+        }
+        self.version = version;
+        edits
+    }
+}
+
+/// text of the opened document if any, otherwise the file content
+pub async fn read(documents: &Documents, path: impl AsRef<Path>) -> Option<String> {
+    if let Some(document) = documents.get(path.as_ref()) {
+        return Some(document.text.clone());
+    }
+    tokio::fs::read_to_string(path).await.ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn change(
+        (from_line, from_char): (u32, u32),
+        (until_line, until_char): (u32, u32),
+        text: &str,
+    ) -> lsp_types::TextDocumentContentChangeEvent {
+        lsp_types::TextDocumentContentChangeEvent {
+            range: Some(lsp_types::Range::new(
+                lsp_types::Position::new(from_line, from_char),
+                lsp_types::Position::new(until_line, until_char),
+            )),
+            range_length: None,
+            text: text.to_owned(),
+        }
+    }
+
+    fn edits(edits: Vec<Edit>) -> Vec<(u32, u32, u32)> {
+        edits
+            .into_iter()
+            .map(|v| (v.from.0, v.until.0, v.len))
+            .collect()
+    }
+
+    #[test]
+    fn edits_spanning_lines() {
+        let mut document = Document::new("fn f() {\n    let 𝕏 = 1;\n}\n".to_owned(), 1);
+        let applied = document.apply(
+            vec![
+                // `𝕏` takes 2 UTF-16 code units
+                change((1, 8), (2, 0), "x = 2;\n"),
+                // applied to the text after the first change
+                change((0, 7), (2, 1), "{}"),
+            ],
+            2,
+            PositionEncoding::Utf16,
+        );
+        assert_eq!(document.text, "fn f() {}\n");
+        assert_eq!(document.version, 2);
+        assert_eq!(edits(applied), [(17, 24, 7), (7, 25, 2)]);
+    }
+
+    #[test]
+    fn edits_in_utf8() {
+        let mut document = Document::new("let é = 1;\nlet b = é;".to_owned(), 1);
+        let applied = document.apply(
+            vec![
+                change((0, 4), (0, 6), "e"),
+                // the end of the last line is clamped
+                change((1, 8), (1, 100), "e;"),
+            ],
+            2,
+            PositionEncoding::Utf8,
+        );
+        assert_eq!(document.text, "let e = 1;\nlet b = e;");
+        assert_eq!(edits(applied), [(4, 5, 1), (19, 21, 2)]);
+
+        // without a range, the whole text is replaced
+        let applied = document.apply(
+            vec![lsp_types::TextDocumentContentChangeEvent {
+                range: None,
+                range_length: None,
+                text: "é".to_owned(),
+            }],
+            3,
+            PositionEncoding::Utf8,
+        );
+        assert_eq!(document.text, "é");
+        assert_eq!(edits(applied), [(0, 21, 1)]);
+    }
+
+    #[test]
+    fn edits_beyond_crlf_line_end() {
+        let mut document = Document::new("let a;\r\nlet b;".to_owned(), 1);
+        let applied = document.apply(
+            vec![change((0, 6), (0, 100), " // a")],
+            2,
+            PositionEncoding::Utf16,
+        );
+        assert_eq!(document.text, "let a; // a\r\nlet b;");
+        assert_eq!(edits(applied), [(6, 6, 5)]);
+    }
+}
@@ -0,0 +1,277 @@
+use crate::{
+    lsp::{borrower, decoration},
+    models::*,
+    utils,
+};
+use std::collections::{BTreeMap, BTreeSet};
+
+/// declarations of the given locals
+fn decls<'a>(functions: &[&'a Function], locals: &[FnLocal]) -> Vec<&'a MirDecl> {
+    functions
+        .iter()
+        .flat_map(|v| &v.decls)
+        .filter(|v| locals.contains(&v.local()))
+        .collect()
+}
+
+/// `line 3` or `lines 4, 10`, 1-based
+fn lines(index: &utils::LineIndex, locs: impl IntoIterator<Item = Loc>) -> String {
+    let lines: BTreeSet<u32> = locs
+        .into_iter()
+        .map(|v| index.line_char(v, utils::PositionEncoding::Utf32).0 + 1)
+        .collect();
+    let list = lines
+        .iter()
+        .map(|v| v.to_string())
+        .collect::<Vec<_>>()
+        .join(", ");
+    if lines.len() == 1 {
+        format!("line {list}")
+    } else {
+        format!("lines {list}")
+    }
+}
+
+/// `s.a` for partial operations on `s`
+fn places<'a>(places: impl IntoIterator<Item = &'a MirPlace>) -> String {
+    let paths: BTreeSet<&str> = places
+        .into_iter()
+        .filter(|v| !v.is_whole())
+        .map(|v| v.path.as_str())
+        .collect();
+    if paths.is_empty() {
+        String::new()
+    } else {
+        let list = paths
+            .iter()
+            .map(|v| format!("`{v}`"))
+            .collect::<Vec<_>>()
+            .join(", ");
+        format!(" ({list})")
+    }
+}
+
+fn summarize(
+    functions: &[&Function],
+    decl: &MirDecl,
+    decos: &[decoration::Deco],
+    index: &utils::LineIndex,
+) -> String {
+    let (name, ty, lives, drop, drop_range, must_live_blame) = match decl {
+        MirDecl::User {
+            name,
+            ty,
+            lives,
+            drop,
+            drop_range,
+            must_live_blame,
+            ..
+        } => (Some(name), ty, lives, drop, drop_range, must_live_blame),
+        MirDecl::Other {
+            ty,
+            lives,
+            drop,
+            drop_range,
+            must_live_blame,
+            ..
+        } => (None, ty, lives, drop, drop_range, must_live_blame),
+    };
+    let local = decl.local();
+    let mut text = match name {
+        Some(name) => format!("**variable `{name}`**: `{ty}`\n"),
+        None => format!("**anonymous variable**: `{ty}`\n"),
+    };
+    if let MirDecl::User { span, .. } = decl {
+        text.push_str(&format!("\n- declared at {}", lines(index, [span.from()])));
+    }
+
+    let mut moves = Vec::new();
+    let mut shared = Vec::new();
+    let mut mutable = Vec::new();
+    let mut outlive = Vec::new();
+    for deco in decos {
+        match deco {
+            decoration::Deco::Move {
+                local: l,
+                place,
+                range,
+                ..
+            } if *l == local => moves.push((range, place)),
+            decoration::Deco::ImmBorrow {
+                local: l,
+                place,
+                range,
+                ..
+            } if *l == local => shared.push((range, place)),
+            decoration::Deco::MutBorrow {
+                local: l,
+                place,
+                range,
+                ..
+            } if *l == local => mutable.push((range, place)),
+            decoration::Deco::Outlive {
+                local: l, range, ..
+            } if *l == local => outlive.push(range),
+            _ => {}
+        }
+    }
+    for (ops, verb) in [
+        (moves, "moved"),
+        (shared, "borrowed immutably"),
+        (mutable, "borrowed mutably"),
+    ] {
+        if !ops.is_empty() {
+            text.push_str(&format!(
+                "\n- {verb} at {}{}",
+                lines(index, ops.iter().map(|v| v.0.from())),
+                places(ops.iter().map(|v| v.1)),
+            ));
+        }
+    }
+
+    let (loans, referents) = borrower::borrowers(functions, &[local]);
+    // the last line where each reference keeps a loan of the variable alive
+    let mut holders: BTreeMap<&str, Loc> = BTreeMap::new();
+    for holder in loans.iter().flat_map(|v| &v.holders) {
+        if let Some(until) = holder.live.iter().map(|v| v.until()).max() {
+            let last = holders.entry(&holder.name).or_insert(until);
+            *last = (*last).max(until);
+        }
+    }
+    if !holders.is_empty() {
+        let list = holders
+            .iter()
+            .map(|(name, until)| format!("`{name}` (until {})", lines(index, [*until])))
+            .collect::<Vec<_>>()
+            .join(", ");
+        text.push_str(&format!("\n- referenced by {list}"));
+    }
+    if !referents.is_empty() {
+        let list = referents
+            .iter()
+            .map(|v| {
+                format!(
+                    "`{}` borrowed at {}",
+                    v.place,
+                    lines(index, [v.range.from()])
+                )
+            })
+            .collect::<Vec<_>>()
+            .join(", ");
+        text.push_str(&format!("\n- refers to {list}"));
+    }
+
+    let live: RangeSet = lives.iter().chain(drop_range).copied().collect();
+    match live.ranges().last() {
+        Some(last) if *drop => {
+            text.push_str(&format!("\n- dropped at {}", lines(index, [last.until()])));
+        }
+        _ => text.push_str("\n- needs no drop"),
+    }
+    if outlive.is_empty() {
+        text.push_str("\n- lives as long as it is used");
+    } else {
+        text.push_str(&format!(
+            "\n- **required to outlive its lifetime** at {}",
+            lines(index, outlive.iter().map(|v| v.from()))
+        ));
+        // the same chain usually keeps the variable alive over several ranges
+        let mut reasons: Vec<(String, MirBlame, bool)> = Vec::new();
+        for blame in must_live_blame {
+            let outlives = outlive
+                .iter()
+                .any(|v| v.from() < blame.range.until() && blame.range.from() < v.until());
+            let key = explain(blame, None);
+            match reasons.iter_mut().find(|(k, _, _)| *k == key) {
+                Some((_, last, shown)) => {
+                    last.range = blame.range;
+                    *shown |= outlives;
+                }
+                None => reasons.push((key, blame.clone(), outlives)),
+            }
+        }
+        for (_, blame, _) in reasons.iter().filter(|v| v.2) {
+            text.push_str(&format!("\n  - {}", explain(blame, Some(index))));
+        }
+    }
+    text
+}
+
+/// explains why a variable must live, e.g.
+/// "`r` borrows `x` → returned via `'a` → caller requires `'a` until line 42";
+/// lines are omitted without `index`
+pub fn explain(blame: &MirBlame, index: Option<&utils::LineIndex>) -> String {
+    let at = |range: Option<Range>| match (index, range) {
+        (Some(index), Some(range)) => format!(" at {}", lines(index, [range.from()])),
+        _ => String::new(),
+    };
+    let until = match index {
+        Some(index) => format!(" until {}", lines(index, [blame.range.until()])),
+        None => String::new(),
+    };
+
+    let place = &blame.place.path;
+    let mut steps = Vec::new();
+    let mut chain = blame.chain.iter().peekable();
+    match chain.peek() {
+        Some(MirBlameStep {
+            region: MirRegion::Variable { name, .. },
+            ..
+        }) => {
+            steps.push(format!(
+                "`{name}` borrows `{place}`{}",
+                at(Some(blame.borrowed))
+            ));
+            chain.next();
+        }
+        _ => steps.push(format!("`{place}` is borrowed{}", at(Some(blame.borrowed)))),
+    }
+    for step in chain {
+        steps.push(match &step.region {
+            MirRegion::Variable { name, .. } => format!("flows into `{name}`{}", at(step.range)),
+            MirRegion::Return { name: Some(name) } => {
+                format!("returned via `{name}`{}", at(step.range))
+            }
+            MirRegion::Return { name: None } => format!("returned{}", at(step.range)),
+            MirRegion::Universal { name: Some(name) } => {
+                format!("caller requires `{name}`{until}")
+            }
+            MirRegion::Universal { name: None } => format!("caller requires it{until}"),
+            MirRegion::Anonymous => continue,
+        });
+    }
+    match blame.chain.last().map(|v| &v.region) {
+        Some(MirRegion::Universal { .. }) => {}
+        Some(MirRegion::Variable { name, .. }) => steps.push(format!("`{name}` is used{until}")),
+        _ => steps.push(format!("a temporary holds it{until}")),
+    }
+    steps.join(" → ")
+}
+
+/// markdown summary of the locals under the cursor
+pub fn hover(functions: &[&Function], pos: Loc, index: &utils::LineIndex) -> Option<String> {
+    let mut selected = decoration::SelectLocal::new(pos);
+    for func in functions {
+        utils::mir_visit(func, &mut selected);
+    }
+    let locals = selected.selected();
+    if locals.is_empty() {
+        return None;
+    }
+
+    let mut calc = decoration::CalcDecos::new(locals.iter().copied());
+    for func in functions {
+        utils::mir_visit(func, &mut calc);
+    }
+    let decos = calc.decorations();
+
+    let summaries: Vec<_> = decls(functions, &locals)
+        .into_iter()
+        .map(|decl| summarize(functions, decl, &decos, index))
+        .collect();
+    if summaries.is_empty() {
+        None
+    } else {
+        Some(summaries.join("\n\n---\n\n"))
+    }
+}
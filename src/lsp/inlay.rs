@@ -0,0 +1,87 @@
+use crate::{models::*, utils};
+use std::collections::{BTreeSet, HashMap, HashSet};
+use tower_lsp::lsp_types;
+
+/// where each local is dropped by `Drop` terminators, and which locals are moved out of
+#[derive(Default)]
+struct CollectDrops {
+    drops: HashMap<FnLocal, Vec<Loc>>,
+    moved: HashSet<FnLocal>,
+}
+impl utils::MirVisitor for CollectDrops {
+    fn visit_stmt(&mut self, stmt: &MirStatement) {
+        if let MirStatement::Assign {
+            rval: Some(MirRval::Move { target_local, .. }),
+            ..
+        } = stmt
+        {
+            self.moved.insert(*target_local);
+        }
+    }
+    fn visit_term(&mut self, term: &MirTerminator) {
+        if let MirTerminator::Drop { local, range } = term {
+            self.drops.entry(*local).or_default().push(range.from());
+        }
+    }
+}
+
+/// `drop(x)` where each user variable is dropped, and `x moved` where one moved out
+/// of is used last
+pub fn inlay_hints(
+    functions: &[&Function],
+    visible: Range,
+    index: &utils::LineIndex,
+    encoding: utils::PositionEncoding,
+) -> Vec<lsp_types::InlayHint> {
+    let mut drops = CollectDrops::default();
+    for func in functions {
+        utils::mir_visit(func, &mut drops);
+    }
+
+    // a variable is also dropped on the unwind path, at the same location
+    let mut hints = BTreeSet::new();
+    for decl in functions.iter().flat_map(|v| &v.decls) {
+        let MirDecl::User {
+            local,
+            name,
+            lives,
+            drop: true,
+            drop_range,
+            ..
+        } = decl
+        else {
+            continue;
+        };
+        if drop_range.is_empty() {
+            // the value is moved out, so nothing is left to drop
+            if !drops.moved.contains(local) {
+                continue;
+            }
+            let lives: RangeSet = lives.iter().copied().collect();
+            if let Some(last) = lives.ranges().last() {
+                hints.insert((last.until(), format!("{name} moved")));
+            }
+        } else if let Some(locs) = drops.drops.get(local) {
+            for loc in locs {
+                hints.insert((*loc, format!("drop({name})")));
+            }
+        } else if let Some(last) = drop_range.iter().map(|v| v.until()).max() {
+            hints.insert((last, format!("drop({name})")));
+        }
+    }
+
+    hints
+        .into_iter()
+        .filter(|(loc, _)| visible.from() <= *loc && *loc <= visible.until())
+        .map(|(loc, label)| lsp_types::InlayHint {
+            position: index.lsp_position(loc, encoding),
+            label: lsp_types::InlayHintLabel::String(label),
+            kind: None,
+            text_edits: None,
+            tooltip: None,
+            padding_left: Some(true),
+            padding_right: Some(true),
+            data: None,
+        })
+        .collect()
+}
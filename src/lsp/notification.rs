@@ -0,0 +1,55 @@
+use crate::lsp::progress;
+use serde::{Deserialize, Serialize};
+use tokio::sync::RwLock;
+use tower_lsp::{Client, lsp_types};
+
+/// analysis results of a file are added or replaced
+pub enum AnalysisUpdated {}
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct AnalysisUpdatedParams {
+    pub uri: lsp_types::Url,
+}
+impl lsp_types::notification::Notification for AnalysisUpdated {
+    type Params = AnalysisUpdatedParams;
+    const METHOD: &'static str = "rustowl/analysisUpdated";
+}
+
+/// the analysis status changed
+pub enum Status {}
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct StatusParams {
+    pub status: progress::AnalysisStatus,
+}
+impl lsp_types::notification::Notification for Status {
+    type Params = StatusParams;
+    const METHOD: &'static str = "rustowl/status";
+}
+
+pub async fn analysis_updated(client: &Client, filenames: impl IntoIterator<Item = String>) {
+    for filename in filenames {
+        if let Ok(uri) = lsp_types::Url::from_file_path(filename) {
+            client
+                .send_notification::<AnalysisUpdated>(AnalysisUpdatedParams { uri })
+                .await;
+        }
+    }
+}
+
+/// set the status and notify the client if it is changed
+pub async fn set_status(
+    client: &Client,
+    status: &RwLock<progress::AnalysisStatus>,
+    new: progress::AnalysisStatus,
+) {
+    let changed = {
+        let mut status = status.write().await;
+        let changed = *status != new;
+        *status = new;
+        changed
+    };
+    if changed {
+        client
+            .send_notification::<Status>(StatusParams { status: new })
+            .await;
+    }
+}
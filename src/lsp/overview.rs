@@ -0,0 +1,91 @@
+use crate::{
+    lsp::{decoration, progress},
+    models::*,
+    utils,
+};
+use std::path::PathBuf;
+use tower_lsp::lsp_types;
+
+/// lines `start..=end`, 0-based
+#[derive(serde::Serialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub struct LineSpan {
+    pub start: u32,
+    pub end: u32,
+}
+
+#[derive(serde::Serialize, Clone, Debug)]
+pub struct Variable {
+    pub local: FnLocal,
+    pub name: String,
+    pub ty: String,
+    pub declared: lsp_types::Range,
+    /// lines where the variable is alive, for gutter rendering
+    pub lifetime: Vec<LineSpan>,
+    pub decorations: Vec<decoration::Deco<lsp_types::Range>>,
+}
+
+#[derive(serde::Serialize, Clone, Debug)]
+pub struct FunctionOverview {
+    pub status: progress::AnalysisStatus,
+    pub path: Option<PathBuf>,
+    /// def path of the function
+    pub function: Option<String>,
+    pub variables: Vec<Variable>,
+}
+
+/// every user variable of the function, in declaration order
+pub fn variables(
+    func: &Function,
+    index: &utils::LineIndex,
+    encoding: utils::PositionEncoding,
+) -> Vec<Variable> {
+    let mut variables: Vec<Variable> = Vec::new();
+    for decl in &func.decls {
+        let MirDecl::User {
+            local,
+            name,
+            span,
+            ty,
+            ..
+        } = decl
+        else {
+            continue;
+        };
+        let mut calc = decoration::CalcDecos::new([*local]);
+        utils::mir_visit(func, &mut calc);
+        let lives: RangeSet = calc
+            .clone()
+            .decorations()
+            .into_iter()
+            .filter_map(|deco| match deco {
+                decoration::Deco::Lifetime { range, .. } => Some(range),
+                _ => None,
+            })
+            .collect();
+        let mut lifetime: Vec<LineSpan> = Vec::new();
+        for range in lives.ranges() {
+            let start = index.line_char(range.from(), encoding).0;
+            let end = index.line_char(range.until(), encoding).0;
+            match lifetime.last_mut() {
+                // ranges on adjacent lines form one span
+                Some(last) if start <= last.end + 1 => last.end = last.end.max(end),
+                _ => lifetime.push(LineSpan { start, end }),
+            }
+        }
+        calc.handle_overlapping();
+        variables.push(Variable {
+            local: *local,
+            name: name.clone(),
+            ty: ty.clone(),
+            declared: index.lsp_range(*span, encoding),
+            lifetime,
+            decorations: calc
+                .decorations()
+                .into_iter()
+                .map(|v| v.to_lsp_range(index, encoding))
+                .collect(),
+        });
+    }
+    variables.sort_by_key(|v| (v.declared.start.line, v.declared.start.character));
+    variables
+}
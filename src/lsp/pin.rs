@@ -0,0 +1,125 @@
+use crate::{
+    lsp::{decoration, progress},
+    models::*,
+    utils,
+};
+use std::path::PathBuf;
+use tower_lsp::lsp_types;
+
+#[derive(serde::Deserialize, Clone, Debug)]
+#[serde(rename_all = "snake_case")]
+pub struct PinRequest {
+    pub document: lsp_types::TextDocumentIdentifier,
+    /// locals selected at the position are pinned or unpinned;
+    /// without it, `rustowl/pin` only returns the pinned locals and `rustowl/unpin` clears them
+    pub position: Option<lsp_types::Position>,
+}
+impl PinRequest {
+    pub fn path(&self) -> Option<PathBuf> {
+        self.document.uri.to_file_path().ok()
+    }
+}
+
+#[derive(serde::Serialize, Clone, Debug)]
+pub struct PinnedLocal {
+    pub local: FnLocal,
+    pub name: Option<String>,
+    /// index of the local in pinned order, to tell apart decorations of each local
+    pub tag: usize,
+}
+
+/// decoration of a pinned local, tagged like the local
+#[derive(serde::Serialize, Clone, Debug)]
+pub struct PinnedDeco<R = Range> {
+    pub tag: usize,
+    #[serde(flatten)]
+    pub deco: decoration::Deco<R>,
+}
+
+#[derive(serde::Serialize, Clone, Debug)]
+pub struct Conflict<R = Range> {
+    pub range: R,
+    pub hover_text: String,
+}
+
+#[derive(serde::Serialize, Clone, Debug)]
+pub struct Pinned {
+    pub status: progress::AnalysisStatus,
+    pub path: Option<PathBuf>,
+    pub locals: Vec<PinnedLocal>,
+    pub decorations: Vec<PinnedDeco<lsp_types::Range>>,
+    pub conflicts: Vec<Conflict<lsp_types::Range>>,
+}
+
+fn name(functions: &[&Function], local: FnLocal) -> Option<String> {
+    functions
+        .iter()
+        .flat_map(|v| &v.decls)
+        .find_map(|decl| match decl {
+            MirDecl::User { local: l, name, .. } if *l == local => Some(name.clone()),
+            _ => None,
+        })
+}
+
+/// decorations of each pinned local, and ranges where one local is mutably borrowed
+/// while another is alive, e.g. a `Vec` pushed while an iterator over it is used
+pub fn pinned(
+    functions: &[&Function],
+    locals: &[FnLocal],
+) -> (Vec<PinnedLocal>, Vec<PinnedDeco>, Vec<Conflict>) {
+    let mut pinned = Vec::new();
+    let mut decorations = Vec::new();
+    // lifetime and mutable borrows of each local, before splitting overlapped parts
+    let mut states = Vec::new();
+    for (tag, local) in locals.iter().enumerate() {
+        let mut calc = decoration::CalcDecos::new([*local]);
+        for func in functions.iter().filter(|v| v.fn_id == local.fn_id) {
+            utils::mir_visit(func, &mut calc);
+        }
+        let mut lifetime = RangeSet::new();
+        let mut mutated = RangeSet::new();
+        for deco in calc.clone().decorations() {
+            match deco {
+                decoration::Deco::Lifetime { range, .. } => lifetime.insert(range),
+                decoration::Deco::MutBorrow { range, .. } => mutated.insert(range),
+                _ => {}
+            }
+        }
+        calc.handle_overlapping();
+        decorations.extend(
+            calc.decorations()
+                .into_iter()
+                .map(|deco| PinnedDeco { tag, deco }),
+        );
+
+        let name = name(functions, *local);
+        let name_str = name
+            .as_ref()
+            .map(|v| format!("`{v}`"))
+            .unwrap_or("anonymous variable".to_owned());
+        states.push((name_str, lifetime, mutated));
+        pinned.push(PinnedLocal {
+            local: *local,
+            name,
+            tag,
+        });
+    }
+
+    let mut conflicts = Vec::new();
+    for (i, (alive_name, lifetime, _)) in states.iter().enumerate() {
+        for (j, (mutated_name, _, mutated)) in states.iter().enumerate() {
+            if i == j {
+                continue;
+            }
+            for range in lifetime.intersection(mutated).into_vec() {
+                conflicts.push(Conflict {
+                    range,
+                    hover_text: format!(
+                        "{mutated_name} is mutably borrowed while {alive_name} is alive"
+                    ),
+                });
+            }
+        }
+    }
+    (pinned, decorations, conflicts)
+}
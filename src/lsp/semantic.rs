@@ -0,0 +1,295 @@
+use crate::{models::*, utils};
+use std::collections::{HashMap, HashSet};
+use tower_lsp::lsp_types;
+
+const MOVED: u32 = 1 << 0;
+const BORROWED: u32 = 1 << 1;
+const MUT_BORROWED: u32 = 1 << 2;
+const DROPPED: u32 = 1 << 3;
+const OUTLIVES: u32 = 1 << 4;
+
+/// every token is a `variable`, and modifiers carry the ownership state in bit order
+pub fn legend() -> lsp_types::SemanticTokensLegend {
+    lsp_types::SemanticTokensLegend {
+        token_types: vec![lsp_types::SemanticTokenType::VARIABLE],
+        token_modifiers: ["moved", "borrowed", "mutBorrowed", "dropped", "outlives"]
+            .into_iter()
+            .map(lsp_types::SemanticTokenModifier::new)
+            .collect(),
+    }
+}
+
+/// modifiers of each use of user variables
+#[derive(Default)]
+struct CollectUses {
+    /// where the local is kept alive only to be dropped, and where it outlives its
+    /// lifetime, with the modifiers applied to uses there
+    states: HashMap<FnLocal, Vec<(RangeSet, u32)>>,
+    names: HashMap<FnLocal, String>,
+    uses: Vec<(FnLocal, Range, u32)>,
+    closures: HashSet<Range>,
+}
+impl utils::MirVisitor for CollectUses {
+    fn visit_decl(&mut self, decl: &MirDecl) {
+        if let MirDecl::User {
+            local,
+            name,
+            span,
+            lives,
+            drop,
+            drop_range,
+            must_live_at,
+            ..
+        } = decl
+        {
+            let states = self.states.entry(*local).or_default();
+            if *drop {
+                states.push((drop_range.iter().copied().collect(), DROPPED));
+            }
+            let live: RangeSet = lives.iter().chain(drop_range).copied().collect();
+            states.push((
+                RangeSet::from(must_live_at.clone()).exclude(&live),
+                OUTLIVES,
+            ));
+            self.names.insert(*local, name.clone());
+            self.uses.push((*local, *span, 0));
+        }
+    }
+    fn visit_closure(&mut self, closure: &MirClosure) {
+        self.closures.insert(closure.range);
+        for capture in &closure.captures {
+            let modifier = match capture.kind {
+                MirCaptureKind::Ref => BORROWED,
+                MirCaptureKind::MutRef => MUT_BORROWED,
+                MirCaptureKind::Move => MOVED,
+                MirCaptureKind::Copy => 0,
+            };
+            self.uses.push((capture.local, capture.range, modifier));
+        }
+    }
+    fn visit_stmt(&mut self, stmt: &MirStatement) {
+        if let MirStatement::Assign { rval, .. } = stmt {
+            match rval {
+                Some(MirRval::Move {
+                    target_local,
+                    range,
+                    ..
+                }) => self.uses.push((*target_local, *range, MOVED)),
+                Some(MirRval::Borrow {
+                    target_local,
+                    range,
+                    mutable,
+                    ..
+                }) => {
+                    let modifier = if *mutable { MUT_BORROWED } else { BORROWED };
+                    self.uses.push((*target_local, *range, modifier));
+                }
+                None => {}
+            }
+        }
+    }
+}
+
+/// the variable name inside the range of its use, e.g. `v` of `&mut v`
+fn identifier(text: &str, index: &utils::LineIndex, range: Range, name: &str) -> Option<Range> {
+    let from = index.byte(range.from()) as usize;
+    let source = text.get(from..index.byte(range.until()) as usize)?;
+    let is_ident = |c: char| c.is_alphanumeric() || c == '_';
+    let (start, _) = source.match_indices(name).find(|(i, _)| {
+        !source[..*i].chars().next_back().is_some_and(is_ident)
+            && !source[i + name.len()..]
+                .chars()
+                .next()
+                .is_some_and(is_ident)
+    })?;
+    let start = from + start;
+    Range::new(
+        index.loc(start as u32),
+        index.loc((start + name.len()) as u32),
+    )
+}
+
+/// semantic tokens of the variables used in the analyzed functions inside `visible`
+///
+/// Overlapping uses are split so that each part has the modifiers of all uses covering it,
+/// and parts spanning several lines are split per line.
+pub fn tokens(
+    functions: &[&Function],
+    visible: Range,
+    text: &str,
+    index: &utils::LineIndex,
+    encoding: utils::PositionEncoding,
+) -> Vec<lsp_types::SemanticToken> {
+    let mut uses = CollectUses::default();
+    for func in functions {
+        utils::mir_visit(func, &mut uses);
+    }
+
+    // each use adds its modifiers on entry and removes them on exit
+    let mut events = Vec::new();
+    for (local, range, modifier) in &uses.uses {
+        // temporaries are not identifiers in the source
+        let Some(state) = uses.states.get(local) else {
+            continue;
+        };
+        // borrows by closure construction are shown at each captured place instead
+        if uses.closures.contains(range) {
+            continue;
+        }
+        // uses whose source does not name the variable, e.g. inside macros, are omitted
+        let Some(range) = identifier(text, index, *range, &uses.names[local]) else {
+            continue;
+        };
+        let Some(range) = Range::new(
+            range.from().max(visible.from()),
+            range.until().min(visible.until()),
+        ) else {
+            continue;
+        };
+        events.push((range.from(), 1, *modifier));
+        events.push((range.until(), -1, *modifier));
+        // states apply only to the part of the use where they hold
+        for (ranges, modifier) in state {
+            for part in ranges.clip(range).into_vec() {
+                events.push((part.from(), 1, *modifier));
+                events.push((part.until(), -1, *modifier));
+            }
+        }
+    }
+    events.sort_by_key(|v| v.0);
+
+    let mut depth = 0;
+    let mut counts = [0; 5];
+    let mut segments = Vec::new();
+    let mut prev = Loc(0);
+    for (loc, delta, modifiers) in events {
+        if 0 < depth && prev < loc {
+            let modifiers = (0..counts.len())
+                .filter(|i| 0 < counts[*i])
+                .fold(0, |acc, i| acc | 1 << i);
+            match segments.last_mut() {
+                Some((_, until, last)) if *until == prev && *last == modifiers => *until = loc,
+                _ => segments.push((prev, loc, modifiers)),
+            }
+        }
+        depth += delta;
+        for (i, count) in counts.iter_mut().enumerate() {
+            if modifiers & 1 << i != 0 {
+                *count += delta;
+            }
+        }
+        prev = loc;
+    }
+
+    let mut tokens = Vec::new();
+    let (mut prev_line, mut prev_start) = (0, 0);
+    for (from, until, modifiers) in segments {
+        let (start_line, _) = index.line_char(from, encoding);
+        let (end_line, _) = index.line_char(until, encoding);
+        for line in start_line..=end_line {
+            // a segment spanning several lines is split at each line end
+            let line_end = index.line_end(line);
+            let (_, start) = index.line_char(from.max(index.index(line, 0, encoding)), encoding);
+            let (_, end) = index.line_char(until.min(line_end), encoding);
+            if end <= start {
+                continue;
+            }
+            let delta_line = line - prev_line;
+            let delta_start = if delta_line == 0 {
+                start - prev_start
+            } else {
+                start
+            };
+            tokens.push(lsp_types::SemanticToken {
+                delta_line,
+                delta_start,
+                length: end - start,
+                token_type: 0,
+                token_modifiers_bitset: modifiers,
+            });
+            (prev_line, prev_start) = (line, start);
+        }
+    }
+    tokens
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{range, user_decl};
+
+    /// `v` declared at `4..9` and borrowed mutably at `17..23`, dropped in `drop_range`
+    fn borrowed_v(drop_range: Vec<Range>) -> Function {
+        let local = FnLocal::new(1, 1);
+        Function {
+            basic_blocks: vec![MirBasicBlock {
+                statements: vec![MirStatement::Assign {
+                    target_local: FnLocal::new(2, 1),
+                    range: range(17, 23),
+                    rval: Some(MirRval::Borrow {
+                        target_local: local,
+                        target_place: MirPlace::new("v", Vec::new()),
+                        range: range(17, 23),
+                        mutable: true,
+                        outlive: None,
+                    }),
+                }],
+                terminator: None,
+            }],
+            decls: vec![user_decl(
+                local,
+                "v",
+                "i32",
+                range(4, 9),
+                vec![range(8, 23)],
+                drop_range,
+            )],
+            ..crate::testing::function(1, "krate", "krate::f", range(0, 23))
+        }
+    }
+
+    /// (delta line, delta start, length, modifiers) of each token
+    fn token_list(func: &Function, text: &str) -> Vec<(u32, u32, u32, u32)> {
+        let index = utils::LineIndex::new(text);
+        tokens(
+            &[func],
+            range(0, index.len()),
+            text,
+            &index,
+            utils::PositionEncoding::Utf16,
+        )
+        .iter()
+        .map(|v| {
+            (
+                v.delta_line,
+                v.delta_start,
+                v.length,
+                v.token_modifiers_bitset,
+            )
+        })
+        .collect()
+    }
+
+    #[test]
+    fn tokens_cover_the_variable_up_to_the_end_of_text() {
+        let text = "let mut v = 0;\nf(&mut v";
+        // `v` of `mut v`, and `v` of `&mut v` as the last character
+        assert_eq!(
+            token_list(&borrowed_v(Vec::new()), text),
+            [(0, 8, 1, 0), (1, 7, 1, MUT_BORROWED)]
+        );
+    }
+
+    #[test]
+    fn dropped_applies_only_inside_the_drop_range() {
+        let text = "let mut v = 0;\nf(&mut v";
+        assert_eq!(
+            token_list(&borrowed_v(vec![range(20, 23)]), text),
+            [(0, 8, 1, 0), (1, 7, 1, MUT_BORROWED | DROPPED)]
+        );
+        assert_eq!(
+            token_list(&borrowed_v(vec![range(10, 14)]), text),
+            [(0, 8, 1, 0), (1, 7, 1, MUT_BORROWED)]
+        );
+    }
+}
@@ -1,52 +1,43 @@
 #![allow(unused)]
 
+use crate::utils::{LineIndex, RangeIndex};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct FnLocal {
     pub id: u32,
-    pub fn_id: u32,
+    /// [`Function::fn_id`] of the function the local belongs to
+    pub fn_id: u64,
 }
 
 impl FnLocal {
-    pub fn new(id: u32, fn_id: u32) -> Self {
+    pub fn new(id: u32, fn_id: u64) -> Self {
         Self { id, fn_id }
     }
 }
 
-#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
 #[serde(transparent)]
 pub struct Loc(pub u32);
 impl Loc {
-    pub fn new(source: &str, byte_pos: u32, offset: u32) -> Self {
-        let byte_pos = byte_pos.saturating_sub(offset);
-        for (i, (byte, _)) in source.char_indices().enumerate() {
-            if byte_pos <= byte as u32 {
-                return Self(i as u32);
-            }
-        }
-        Self(0)
+    pub fn new(source: &LineIndex, byte_pos: u32, offset: u32) -> Self {
+        source.loc(byte_pos.saturating_sub(offset))
     }
 }
 impl std::ops::Add<i32> for Loc {
     type Output = Loc;
+    /// saturates at `0` and `u32::MAX`
     fn add(self, rhs: i32) -> Self::Output {
-        if rhs < 0 && (self.0 as i32) < -rhs {
-            Loc(0)
-        } else {
-            Loc(self.0 + rhs as u32)
-        }
+        Loc(self.0.saturating_add_signed(rhs))
     }
 }
 impl std::ops::Sub<i32> for Loc {
     type Output = Loc;
+    /// saturates at `0` and `u32::MAX`
     fn sub(self, rhs: i32) -> Self::Output {
-        if 0 < rhs && (self.0 as i32) < rhs {
-            Loc(0)
-        } else {
-            Loc(self.0 - rhs as u32)
-        }
+        Loc((i64::from(self.0) - i64::from(rhs)).clamp(0, i64::from(u32::MAX)) as u32)
     }
 }
 impl From<u32> for Loc {
@@ -60,7 +51,7 @@ impl From<Loc> for u32 {
     }
 }
 
-#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Hash, Debug)]
 pub struct Range {
     from: Loc,
     until: Loc,
@@ -82,6 +73,198 @@ impl Range {
     pub fn size(&self) -> u32 {
         self.until.0 - self.from.0
     }
+    /// `true` if the edit replacing `from..until` changes text inside the range
+    pub fn is_edited(&self, from: Loc, until: Loc) -> bool {
+        from < self.until && self.from < until
+    }
+    /// moves the range through an edit replacing `from..until` with `len` characters
+    pub fn shift(self, from: Loc, until: Loc, len: u32) -> Self {
+        let delta = (i64::from(len) - i64::from(until.0.saturating_sub(from.0)))
+            .clamp(i64::from(i32::MIN), i64::from(i32::MAX)) as i32;
+        if self.until <= from {
+            self
+        } else if until <= self.from {
+            Self {
+                from: self.from + delta,
+                until: self.until + delta,
+            }
+        } else {
+            // the range spans the edit
+            let until = (self.until + delta).max(from + len as i32);
+            Self {
+                from: self.from.min(from),
+                until: until.max(self.from.min(from) + 1),
+            }
+        }
+    }
+}
+
+/// Set of locations stored as sorted ranges, which neither overlap nor touch
+///
+/// Ranges are half-open, so `[0, 2)` and `[2, 4)` are merged into `[0, 4)`.
+/// Set operations run in linear time of the number of ranges.
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Default, Debug)]
+#[serde(transparent)]
+pub struct RangeSet(Vec<Range>);
+impl RangeSet {
+    pub fn new() -> Self {
+        Self(Vec::new())
+    }
+    pub fn ranges(&self) -> &[Range] {
+        &self.0
+    }
+    pub fn into_vec(self) -> Vec<Range> {
+        self.0
+    }
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+    pub fn contains(&self, loc: Loc) -> bool {
+        let i = self.0.partition_point(|v| v.until <= loc);
+        self.0.get(i).is_some_and(|v| v.from <= loc)
+    }
+    /// appends a range which does not start before the last one
+    fn push(&mut self, range: Range) {
+        match self.0.last_mut() {
+            Some(last) if range.from <= last.until => last.until = last.until.max(range.until),
+            _ => self.0.push(range),
+        }
+    }
+    pub fn insert(&mut self, range: Range) {
+        // ranges overlapping or touching the inserted one
+        let start = self.0.partition_point(|v| v.until < range.from);
+        let end = self.0.partition_point(|v| v.from <= range.until);
+        let merged = if start < end {
+            Range {
+                from: self.0[start].from.min(range.from),
+                until: self.0[end - 1].until.max(range.until),
+            }
+        } else {
+            range
+        };
+        self.0.splice(start..end, [merged]);
+    }
+    /// the part of the set inside the range
+    pub fn clip(&self, range: Range) -> Self {
+        let start = self.0.partition_point(|v| v.until <= range.from);
+        let end = self.0.partition_point(|v| v.from < range.until);
+        Self(
+            self.0[start..end.max(start)]
+                .iter()
+                .map(|v| Range {
+                    from: v.from.max(range.from),
+                    until: v.until.min(range.until),
+                })
+                .collect(),
+        )
+    }
+    pub fn union(&self, other: &Self) -> Self {
+        let mut union = Self::new();
+        let (mut a, mut b) = (self.0.iter().peekable(), other.0.iter().peekable());
+        loop {
+            let next = match (a.peek(), b.peek()) {
+                (Some(x), Some(y)) if x.from <= y.from => a.next(),
+                (Some(_), Some(_)) => b.next(),
+                (Some(_), None) => a.next(),
+                (None, Some(_)) => b.next(),
+                (None, None) => break,
+            };
+            union.push(*next.unwrap());
+        }
+        union
+    }
+    pub fn intersection(&self, other: &Self) -> Self {
+        let mut intersection = Vec::new();
+        let (mut i, mut j) = (0, 0);
+        while i < self.0.len() && j < other.0.len() {
+            let (a, b) = (self.0[i], other.0[j]);
+            if let Some(common) = Range::new(a.from.max(b.from), a.until.min(b.until)) {
+                intersection.push(common);
+            }
+            if a.until < b.until {
+                i += 1;
+            } else {
+                j += 1;
+            }
+        }
+        Self(intersection)
+    }
+    pub fn difference(&self, other: &Self) -> Self {
+        let mut difference = Vec::new();
+        let mut j = 0;
+        for range in &self.0 {
+            let mut from = range.from;
+            while j < other.0.len() && other.0[j].until <= from {
+                j += 1;
+            }
+            let mut k = j;
+            while k < other.0.len() && other.0[k].from < range.until {
+                if let Some(piece) = Range::new(from, other.0[k].from) {
+                    difference.push(piece);
+                }
+                from = from.max(other.0[k].until);
+                k += 1;
+            }
+            if let Some(piece) = Range::new(from, range.until) {
+                difference.push(piece);
+            }
+        }
+        Self(difference)
+    }
+    /// `self` without `other`, also excluding the location before and after each
+    /// excluded part
+    pub fn exclude(&self, other: &Self) -> Self {
+        let margins: Self = self
+            .intersection(other)
+            .0
+            .into_iter()
+            .map(|v| Range {
+                from: v.from - 1,
+                until: v.until + 1,
+            })
+            .collect();
+        self.difference(&margins)
+    }
+    /// locations covered by more than one of the ranges
+    pub fn overlaps(ranges: &[Range]) -> Self {
+        let mut bounds: Vec<_> = ranges
+            .iter()
+            .flat_map(|v| [(v.from, 1), (v.until, -1)])
+            .collect();
+        // ends come before starts at the same location, as ranges are half-open
+        bounds.sort();
+        let mut overlaps = Self::new();
+        let mut depth = 0;
+        let mut from = Loc(0);
+        for (loc, delta) in bounds {
+            if depth == 2 && delta < 0 {
+                if let Some(range) = Range::new(from, loc) {
+                    overlaps.push(range);
+                }
+            }
+            depth += delta;
+            if depth == 2 && 0 < delta {
+                from = loc;
+            }
+        }
+        overlaps
+    }
+}
+impl FromIterator<Range> for RangeSet {
+    fn from_iter<T: IntoIterator<Item = Range>>(iter: T) -> Self {
+        let mut ranges: Vec<_> = iter.into_iter().collect();
+        ranges.sort_by_key(|v| v.from);
+        let mut set = Self::new();
+        for range in ranges {
+            set.push(range);
+        }
+        set
+    }
+}
+impl From<Vec<Range>> for RangeSet {
+    fn from(ranges: Vec<Range>) -> Self {
+        ranges.into_iter().collect()
+    }
 }
 
 #[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
@@ -136,9 +319,84 @@ pub enum Item {
     Function { span: Range, mir: Function },
 }
 
+/// source of [`File::generation`], unique in the process
+static GENERATION: AtomicU64 = AtomicU64::new(0);
+fn next_generation() -> u64 {
+    GENERATION.fetch_add(1, Ordering::Relaxed)
+}
+
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct File {
     pub items: Vec<Function>,
+    /// indices of `items` by function span, rebuilt by [`File::reindex`]
+    #[serde(skip)]
+    index: RangeIndex<usize>,
+    #[serde(skip, default = "next_generation")]
+    generation: u64,
+}
+impl File {
+    pub fn new(items: Vec<Function>) -> Self {
+        let mut file = Self {
+            items: Vec::new(),
+            index: RangeIndex::default(),
+            generation: next_generation(),
+        };
+        for item in items {
+            file.insert(item);
+        }
+        file.reindex();
+        file
+    }
+    /// adds the result of a function, or replaces the older one
+    ///
+    /// A function compiled into several crates, e.g. a library and its test target, has
+    /// a different key in each crate but the same source, so only one result is kept.
+    /// Call [`File::reindex`] after inserting.
+    pub fn insert(&mut self, item: Function) {
+        let same =
+            |v: &&mut Function| v.key == item.key || (v.span == item.span && v.path == item.path);
+        match self.items.iter_mut().find(same) {
+            Some(old) => *old = item,
+            None => self.items.push(item),
+        }
+    }
+    /// rebuild the index after `items` are modified
+    pub fn reindex(&mut self) {
+        self.index = RangeIndex::new(self.items.iter().enumerate().map(|(i, v)| (v.span, i)));
+        self.generation = next_generation();
+    }
+    /// changed whenever `items` are modified, e.g. by new results or an edit
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+    /// functions with results up to date, which excludes ones edited after analysis
+    pub fn functions(&self) -> impl Iterator<Item = &Function> {
+        self.items.iter().filter(|v| !v.stale)
+    }
+    /// up-to-date functions enclosing the location, including closures and their parents
+    pub fn functions_at(&self, loc: Loc) -> impl Iterator<Item = &Function> {
+        self.index
+            .find(loc)
+            .into_iter()
+            .map(|i| &self.items[*i])
+            .filter(|v| !v.stale)
+    }
+    /// the innermost function enclosing the location
+    pub fn innermost_at(&self, loc: Loc) -> Option<&Function> {
+        self.functions_at(loc).min_by_key(|v| v.span.size())
+    }
+    /// moves results through an edit replacing `from..until` with `len` characters;
+    /// functions changed by the edit are kept but marked stale, and hidden from
+    /// [`File::functions`] until analyzed again
+    pub fn edit(&mut self, from: Loc, until: Loc, len: u32) {
+        for item in &mut self.items {
+            if item.span.is_edited(from, until) {
+                item.stale = true;
+            }
+            item.map_ranges(&|range| range.shift(from, until, len));
+        }
+        self.reindex();
+    }
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
@@ -161,33 +419,121 @@ impl Workspace {
 #[serde(transparent)]
 pub struct Crate(pub HashMap<String, File>);
 impl Crate {
+    /// merge results into `self`, and reindex the files they belong to
+    ///
+    /// Stale functions of the files are dropped, since ones still in the source are
+    /// analyzed again and the others have been deleted.
     pub fn merge(&mut self, other: Self) {
         let Crate(files) = other;
         for (file, mir) in files {
             if let Some(insert) = self.0.get_mut(&file) {
-                insert.items.extend_from_slice(&mir.items);
-                insert.items.dedup_by(|a, b| a.fn_id == b.fn_id);
+                insert.items.retain(|v| !v.stale);
+                for item in mir.items {
+                    insert.insert(item);
+                }
+                insert.reindex();
             } else {
-                self.0.insert(file, mir);
+                self.0.insert(file, File::new(mir.items));
             }
         }
     }
 }
 
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Hash, Debug)]
+#[serde(rename_all = "snake_case", tag = "type")]
+pub enum MirProjection {
+    Deref,
+    Field { index: u32, name: String },
+    Index,
+    Subslice,
+    Downcast { variant: String },
+}
+
+/// Place path relative to its base local, e.g. `s.a` or `*r`
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Hash, Debug)]
+pub struct MirPlace {
+    pub projection: Vec<MirProjection>,
+    pub path: String,
+}
+impl MirPlace {
+    pub fn new(base: &str, projection: Vec<MirProjection>) -> Self {
+        let mut path = base.to_owned();
+        for (i, elem) in projection.iter().enumerate() {
+            match elem {
+                MirProjection::Deref => {
+                    // field access auto-derefs, so `(*r).a` is shown as `r.a`
+                    if !matches!(projection.get(i + 1), Some(MirProjection::Field { .. })) {
+                        path = format!("*{path}");
+                    }
+                }
+                MirProjection::Field { name, .. } => path = format!("{path}.{name}"),
+                MirProjection::Index => path = format!("{path}[_]"),
+                MirProjection::Subslice => path = format!("{path}[..]"),
+                MirProjection::Downcast { variant } => path = format!("({path} as {variant})"),
+            }
+        }
+        Self { projection, path }
+    }
+    /// `true` if the place is the whole local without any projection
+    pub fn is_whole(&self) -> bool {
+        self.projection.is_empty()
+    }
+    /// `true` if one place contains the other, so accesses to them may overlap
+    pub fn overlaps(&self, other: &Self) -> bool {
+        self.projection
+            .iter()
+            .zip(other.projection.iter())
+            .all(|(a, b)| a == b)
+    }
+}
+
+/// Borrowed ranges of one place of a local
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct MirPlaceBorrow {
+    pub place: MirPlace,
+    pub shared_borrow: Vec<Range>,
+    pub mutable_borrow: Vec<Range>,
+}
+impl MirPlaceBorrow {
+    pub fn map_ranges(&mut self, f: &impl Fn(Range) -> Range) {
+        map_all(&mut self.shared_borrow, f);
+        map_all(&mut self.mutable_borrow, f);
+    }
+}
+
+fn map_all(ranges: &mut [Range], f: &impl Fn(Range) -> Range) {
+    for range in ranges {
+        *range = f(*range);
+    }
+}
+
 #[derive(Serialize, Deserialize, Clone, Debug)]
 #[serde(rename_all = "snake_case", tag = "type")]
 pub enum MirRval {
     Move {
         target_local: FnLocal,
+        target_place: MirPlace,
         range: Range,
     },
     Borrow {
         target_local: FnLocal,
+        target_place: MirPlace,
         range: Range,
         mutable: bool,
         outlive: Option<Range>,
     },
 }
+impl MirRval {
+    pub fn map_ranges(&mut self, f: &impl Fn(Range) -> Range) {
+        match self {
+            Self::Move { range, .. } => *range = f(*range),
+            Self::Borrow { range, outlive, .. } => {
+                *range = f(*range);
+                *outlive = outlive.map(f);
+            }
+        }
+    }
+}
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
 #[serde(rename_all = "snake_case", tag = "type")]
@@ -206,6 +552,19 @@ pub enum MirStatement {
         rval: Option<MirRval>,
     },
 }
+impl MirStatement {
+    pub fn map_ranges(&mut self, f: &impl Fn(Range) -> Range) {
+        match self {
+            Self::StorageLive { range, .. } | Self::StorageDead { range, .. } => *range = f(*range),
+            Self::Assign { range, rval, .. } => {
+                *range = f(*range);
+                if let Some(rval) = rval {
+                    rval.map_ranges(f);
+                }
+            }
+        }
+    }
+}
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
 #[serde(rename_all = "snake_case", tag = "type")]
@@ -217,15 +576,85 @@ pub enum MirTerminator {
     Call {
         destination_local: FnLocal,
         fn_span: Range,
+        /// the callee is `Clone::clone`
+        #[serde(default)]
+        clone: bool,
     },
     Other,
 }
+impl MirTerminator {
+    pub fn map_ranges(&mut self, f: &impl Fn(Range) -> Range) {
+        match self {
+            Self::Drop { range, .. } => *range = f(*range),
+            Self::Call { fn_span, .. } => *fn_span = f(*fn_span),
+            Self::Other => {}
+        }
+    }
+}
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct MirBasicBlock {
     pub statements: Vec<MirStatement>,
     pub terminator: Option<MirTerminator>,
 }
+impl MirBasicBlock {
+    pub fn map_ranges(&mut self, f: &impl Fn(Range) -> Range) {
+        for statement in &mut self.statements {
+            statement.map_ranges(f);
+        }
+        if let Some(terminator) = &mut self.terminator {
+            terminator.map_ranges(f);
+        }
+    }
+}
+
+/// A region a loan flows into
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Debug)]
+#[serde(rename_all = "snake_case", tag = "type")]
+pub enum MirRegion {
+    /// a region in the type of a user variable
+    Variable {
+        local: FnLocal,
+        name: String,
+    },
+    /// a region in the return type, e.g. `'a` of `-> &'a str`
+    Return {
+        name: Option<String>,
+    },
+    /// a region of the signature, which the caller decides how long lives
+    Universal {
+        name: Option<String>,
+    },
+    Anonymous,
+}
+
+/// A link of the chain keeping a loan alive
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct MirBlameStep {
+    pub region: MirRegion,
+    /// where the loan flows into the region
+    pub range: Option<Range>,
+}
+
+/// Why a variable must live in a range
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct MirBlame {
+    pub range: Range,
+    pub place: MirPlace,
+    /// where the place is borrowed
+    pub borrowed: Range,
+    /// regions from the one the loan is created in to the one live in `range`
+    pub chain: Vec<MirBlameStep>,
+}
+impl MirBlame {
+    pub fn map_ranges(&mut self, f: &impl Fn(Range) -> Range) {
+        self.range = f(self.range);
+        self.borrowed = f(self.borrowed);
+        for step in &mut self.chain {
+            step.range = step.range.map(f);
+        }
+    }
+}
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
 #[serde(tag = "type", rename_all = "snake_case")]
@@ -236,27 +665,510 @@ pub enum MirDecl {
         span: Range,
         ty: String,
         lives: Vec<Range>,
-        shared_borrow: Vec<Range>,
-        mutable_borrow: Vec<Range>,
+        place_borrows: Vec<MirPlaceBorrow>,
         drop: bool,
         drop_range: Vec<Range>,
         must_live_at: Vec<Range>,
+        must_live_blame: Vec<MirBlame>,
+        held_across_await: Vec<Range>,
     },
     Other {
         local: FnLocal,
         ty: String,
         lives: Vec<Range>,
-        shared_borrow: Vec<Range>,
-        mutable_borrow: Vec<Range>,
+        place_borrows: Vec<MirPlaceBorrow>,
         drop: bool,
         drop_range: Vec<Range>,
         must_live_at: Vec<Range>,
+        must_live_blame: Vec<MirBlame>,
+        held_across_await: Vec<Range>,
+    },
+}
+impl MirDecl {
+    pub fn local(&self) -> FnLocal {
+        match self {
+            Self::User { local, .. } | Self::Other { local, .. } => *local,
+        }
+    }
+    pub fn map_ranges(&mut self, f: &impl Fn(Range) -> Range) {
+        let (lives, place_borrows, drop_range, must_live_at, must_live_blame, held) = match self {
+            Self::User {
+                span,
+                lives,
+                place_borrows,
+                drop_range,
+                must_live_at,
+                must_live_blame,
+                held_across_await,
+                ..
+            } => {
+                *span = f(*span);
+                (
+                    lives,
+                    place_borrows,
+                    drop_range,
+                    must_live_at,
+                    must_live_blame,
+                    held_across_await,
+                )
+            }
+            Self::Other {
+                lives,
+                place_borrows,
+                drop_range,
+                must_live_at,
+                must_live_blame,
+                held_across_await,
+                ..
+            } => (
+                lives,
+                place_borrows,
+                drop_range,
+                must_live_at,
+                must_live_blame,
+                held_across_await,
+            ),
+        };
+        for ranges in [lives, drop_range, must_live_at, held] {
+            map_all(ranges, f);
+        }
+        for place_borrow in place_borrows {
+            place_borrow.map_ranges(f);
+        }
+        for blame in must_live_blame {
+            blame.map_ranges(f);
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum MirClosureKind {
+    Fn,
+    FnMut,
+    FnOnce,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum MirCaptureKind {
+    Ref,
+    MutRef,
+    Move,
+    Copy,
+}
+
+/// A variable of the enclosing function captured by a closure
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct MirCapture {
+    pub local: FnLocal,
+    pub place: MirPlace,
+    pub kind: MirCaptureKind,
+    /// where the captured place is used in the closure body
+    pub range: Range,
+}
+
+/// A closure constructed in the function
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct MirClosure {
+    /// local the closure is assigned to
+    pub local: FnLocal,
+    /// `fn_id` of the closure body
+    pub fn_id: u64,
+    pub kind: MirClosureKind,
+    pub range: Range,
+    pub captures: Vec<MirCapture>,
+}
+impl MirClosure {
+    pub fn map_ranges(&mut self, f: &impl Fn(Range) -> Range) {
+        self.range = f(self.range);
+        for capture in &mut self.captures {
+            capture.range = f(capture.range);
+        }
+    }
+}
+
+/// A variable holding the reference created by a loan
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct MirLoanHolder {
+    pub local: FnLocal,
+    /// where the variable keeps the loan alive
+    pub live: Vec<Range>,
+}
+
+/// A loan created by borrowing a place
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct MirLoan {
+    /// borrowed local
+    pub local: FnLocal,
+    pub place: MirPlace,
+    pub mutable: bool,
+    /// where the place is borrowed
+    pub range: Range,
+    /// user variables whose regions contain the loan while it is live
+    pub holders: Vec<MirLoanHolder>,
+}
+impl MirLoan {
+    pub fn map_ranges(&mut self, f: &impl Fn(Range) -> Range) {
+        self.range = f(self.range);
+        for holder in &mut self.holders {
+            map_all(&mut holder.live, f);
+        }
+    }
+}
+
+/// Borrow check error found by Polonius
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "snake_case", tag = "type")]
+pub enum MirBorrowError {
+    /// a loan is invalidated while it is still live
+    Loan {
+        local: FnLocal,
+        place: MirPlace,
+        mutable: bool,
+        borrowed: Range,
+        invalidated: Range,
     },
+    /// a required lifetime relation between regions does not hold
+    Subset {
+        range: Range,
+        /// the region required to outlive `shorter`
+        longer: MirRegion,
+        shorter: MirRegion,
+        /// other points requiring the relation
+        points: Vec<Range>,
+    },
+    /// a (partially) moved value is accessed
+    Move {
+        local: FnLocal,
+        place: MirPlace,
+        moved: Vec<Range>,
+        accessed: Range,
+    },
+}
+impl MirBorrowError {
+    pub fn map_ranges(&mut self, f: &impl Fn(Range) -> Range) {
+        match self {
+            Self::Loan {
+                borrowed,
+                invalidated,
+                ..
+            } => {
+                *borrowed = f(*borrowed);
+                *invalidated = f(*invalidated);
+            }
+            Self::Subset { range, points, .. } => {
+                *range = f(*range);
+                map_all(points, f);
+            }
+            Self::Move {
+                moved, accessed, ..
+            } => {
+                map_all(moved, f);
+                *accessed = f(*accessed);
+            }
+        }
+    }
+}
+
+/// Identity of a function which is stable across runs and crates
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Hash, Debug)]
+pub struct FnKey {
+    pub krate: String,
+    pub def_path_hash: String,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct Function {
-    pub fn_id: u32,
+    /// id derived from [`FnKey`], which tells apart functions of different crates
+    pub fn_id: u64,
+    pub key: FnKey,
+    /// human-readable def path, e.g. `krate::module::Type::method`
+    pub path: String,
+    pub signature: String,
+    pub span: Range,
     pub basic_blocks: Vec<MirBasicBlock>,
     pub decls: Vec<MirDecl>,
+    pub closures: Vec<MirClosure>,
+    pub loans: Vec<MirLoan>,
+    pub errors: Vec<MirBorrowError>,
+    /// the function is edited after analysis, so its ranges are approximate
+    #[serde(default)]
+    pub stale: bool,
+}
+impl Function {
+    pub fn map_ranges(&mut self, f: &impl Fn(Range) -> Range) {
+        self.span = f(self.span);
+        for bb in &mut self.basic_blocks {
+            bb.map_ranges(f);
+        }
+        for decl in &mut self.decls {
+            decl.map_ranges(f);
+        }
+        for closure in &mut self.closures {
+            closure.map_ranges(f);
+        }
+        for loan in &mut self.loans {
+            loan.map_ranges(f);
+        }
+        for error in &mut self.errors {
+            error.map_ranges(f);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{random, range};
+
+    fn function(krate: &str, path: &str, span: Range) -> Function {
+        crate::testing::function(0, krate, path, span)
+    }
+
+    /// pseudo-random ranges within `0..64`
+    fn random_ranges(seed: &mut u32, count: u32) -> Vec<Range> {
+        (0..count)
+            .map(|_| {
+                let from = random(seed, 60);
+                range(from, from + 1 + random(seed, 8))
+            })
+            .collect()
+    }
+
+    fn covers(ranges: &[Range], loc: u32) -> usize {
+        ranges
+            .iter()
+            .filter(|r| r.from().0 <= loc && loc < r.until().0)
+            .count()
+    }
+
+    /// `common_range`, `eliminated_ranges` and `exclude_ranges` replaced by `RangeSet`
+    mod former {
+        use super::super::*;
+
+        pub fn common_range(r1: Range, r2: Range) -> Option<Range> {
+            if r2.from() < r1.from() {
+                return common_range(r2, r1);
+            }
+            if r1.until() < r2.from() {
+                return None;
+            }
+            Range::new(r2.from(), r1.until().min(r2.until()))
+        }
+
+        pub fn common_ranges(ranges: &[Range]) -> Vec<Range> {
+            let mut common_ranges = Vec::new();
+            for i in 0..ranges.len() {
+                for j in i + 1..ranges.len() {
+                    if let Some(common) = common_range(ranges[i], ranges[j]) {
+                        common_ranges.push(common);
+                    }
+                }
+            }
+            eliminated_ranges(common_ranges)
+        }
+
+        pub fn eliminated_ranges(mut ranges: Vec<Range>) -> Vec<Range> {
+            let mut i = 0;
+            'outer: while i < ranges.len() {
+                for j in 0..ranges.len() {
+                    let (r1, r2) = (ranges[i], ranges[j]);
+                    if i != j
+                        && (common_range(r1, r2).is_some()
+                            || r1.until() == r2.from()
+                            || r2.until() == r1.from())
+                    {
+                        ranges[i] =
+                            Range::new(r1.from().min(r2.from()), r1.until().max(r2.until()))
+                                .unwrap();
+                        ranges.remove(j);
+                        continue 'outer;
+                    }
+                }
+                i += 1;
+            }
+            // the former result was in no particular order
+            ranges.sort_by_key(|v| v.from());
+            ranges
+        }
+
+        pub fn exclude_ranges(mut from: Vec<Range>, excludes: Vec<Range>) -> Vec<Range> {
+            let mut i = 0;
+            'outer: while i < from.len() {
+                for exclude in &excludes {
+                    if let Some(common) = common_range(from[i], *exclude) {
+                        if let Some(r) = Range::new(from[i].from(), common.from() - 1) {
+                            from.push(r);
+                        }
+                        if let Some(r) = Range::new(common.until() + 1, from[i].until()) {
+                            from.push(r);
+                        }
+                        from.remove(i);
+                        continue 'outer;
+                    }
+                }
+                i += 1;
+            }
+            eliminated_ranges(from)
+        }
+    }
+
+    #[test]
+    fn range_set_operations() {
+        let mut seed = 7;
+        for _ in 0..200 {
+            let (a, b) = (random_ranges(&mut seed, 6), random_ranges(&mut seed, 6));
+            let (set_a, set_b) = (RangeSet::from(a.clone()), RangeSet::from(b.clone()));
+            let union = set_a.union(&set_b);
+            let intersection = set_a.intersection(&set_b);
+            let difference = set_a.difference(&set_b);
+
+            // normalized: sorted, disjoint and not adjacent
+            for set in [&set_a, &union, &intersection, &difference] {
+                assert!(set.ranges().windows(2).all(|w| w[0].until() < w[1].from()));
+            }
+            for loc in 0..72 {
+                let (in_a, in_b) = (0 < covers(&a, loc), 0 < covers(&b, loc));
+                assert_eq!(set_a.contains(Loc(loc)), in_a);
+                assert_eq!(union.contains(Loc(loc)), in_a || in_b);
+                assert_eq!(intersection.contains(Loc(loc)), in_a && in_b);
+                assert_eq!(difference.contains(Loc(loc)), in_a && !in_b);
+            }
+
+            let all: Vec<_> = a.iter().chain(b.iter()).copied().collect();
+            assert_eq!(set_a.ranges(), former::eliminated_ranges(a.clone()));
+            assert_eq!(
+                RangeSet::overlaps(&all).ranges(),
+                former::common_ranges(&all)
+            );
+            assert_eq!(
+                set_a.exclude(&set_b).ranges(),
+                former::exclude_ranges(set_a.ranges().to_vec(), set_b.ranges().to_vec())
+            );
+
+            let mut inserted = RangeSet::new();
+            for range in &all {
+                inserted.insert(*range);
+            }
+            assert_eq!(inserted, RangeSet::from(all.clone()));
+
+            let range = range(random(&mut seed, 40), 41 + random(&mut seed, 20));
+            assert_eq!(
+                union.clip(range),
+                union.intersection(&RangeSet::from(vec![range]))
+            );
+        }
+    }
+
+    #[test]
+    fn exclude_drops_neighbors_of_excluded_ranges() {
+        let set = RangeSet::from(vec![range(0, 10)]);
+        let excluded = RangeSet::from(vec![range(3, 5)]);
+        assert_eq!(
+            set.difference(&excluded).ranges(),
+            [range(0, 3), range(5, 10)]
+        );
+        assert_eq!(set.exclude(&excluded).ranges(), [range(0, 2), range(6, 10)]);
+    }
+
+    #[test]
+    fn functions_of_several_crates_are_merged() {
+        let file = |items| Crate(HashMap::from([("lib.rs".to_owned(), File::new(items))]));
+        let mut krate = file(vec![function("lib", "f", range(0, 10))]);
+        // the test target has the same function and one only compiled for tests
+        krate.merge(file(vec![
+            function("lib_test", "f", range(0, 10)),
+            function("lib_test", "tests::g", range(20, 30)),
+        ]));
+        let items = &krate.0["lib.rs"].items;
+        assert_eq!(items.len(), 2);
+        assert_eq!(items[0].key.krate, "lib_test");
+
+        // a newer result of the same function replaces the old one, even if moved
+        krate.merge(file(vec![function("lib_test", "tests::g", range(25, 35))]));
+        let file = &krate.0["lib.rs"];
+        assert_eq!(file.items.len(), 2);
+        assert_eq!(file.innermost_at(Loc(33)).unwrap().path, "tests::g");
+    }
+
+    #[test]
+    fn deleted_functions_are_dropped_on_merge() {
+        let file = |items| Crate(HashMap::from([("lib.rs".to_owned(), File::new(items))]));
+        let mut krate = file(vec![
+            function("lib", "f", range(0, 10)),
+            function("lib", "g", range(20, 30)),
+        ]);
+        // `g` is deleted by an edit, and only `f` is analyzed again
+        krate.0.get_mut("lib.rs").unwrap().items[1].stale = true;
+        krate.merge(file(vec![function("lib", "f", range(0, 12))]));
+        let items = &krate.0["lib.rs"].items;
+        assert_eq!(items.len(), 1);
+        assert_eq!(items[0].path, "f");
+    }
+
+    #[test]
+    fn generation_changes_on_merge_and_edit() {
+        let file = |items| Crate(HashMap::from([("lib.rs".to_owned(), File::new(items))]));
+        let mut krate = file(vec![function("lib", "f", range(0, 10))]);
+        let created = krate.0["lib.rs"].generation();
+        krate.merge(file(vec![function("lib", "f", range(0, 10))]));
+        let merged = krate.0["lib.rs"].generation();
+        assert_ne!(created, merged);
+        krate.0.get_mut("lib.rs").unwrap().edit(Loc(2), Loc(2), 1);
+        assert_ne!(krate.0["lib.rs"].generation(), merged);
+    }
+
+    #[test]
+    fn loc_arithmetic_saturates() {
+        assert_eq!(Loc(5) + -3, Loc(2));
+        assert_eq!(Loc(2) + -5, Loc(0));
+        assert_eq!(Loc(u32::MAX) + 1, Loc(u32::MAX));
+        assert_eq!(Loc(5) - 3, Loc(2));
+        assert_eq!(Loc(2) - 5, Loc(0));
+        assert_eq!(Loc(2) - -3, Loc(5));
+        assert_eq!(Loc(u32::MAX) - -1, Loc(u32::MAX));
+    }
+
+    #[test]
+    fn shift_through_edits() {
+        let r = range(20, 30);
+        // (edit from, edit until, inserted length, expected)
+        let cases = [
+            // before the range
+            ((2, 2, 3), (23, 33)),
+            ((2, 7, 0), (15, 25)),
+            ((2, 7, 1), (16, 26)),
+            // touching the start
+            ((20, 20, 4), (24, 34)),
+            ((15, 20, 0), (15, 25)),
+            // inside the range
+            ((22, 22, 3), (20, 33)),
+            ((22, 25, 0), (20, 27)),
+            ((22, 25, 1), (20, 28)),
+            // overlapping the start or the end
+            ((15, 25, 0), (15, 20)),
+            ((25, 35, 2), (20, 27)),
+            // covering the whole range
+            ((15, 35, 0), (15, 16)),
+            ((15, 35, 2), (15, 17)),
+            // touching the end and after the range
+            ((30, 30, 3), (20, 30)),
+            ((30, 40, 0), (20, 30)),
+            ((40, 45, 1), (20, 30)),
+        ];
+        for ((from, until, len), (expected_from, expected_until)) in cases {
+            assert_eq!(
+                r.shift(Loc(from), Loc(until), len),
+                range(expected_from, expected_until),
+                "edit {from}..{until} with {len} characters"
+            );
+        }
+    }
+
+    #[test]
+    fn shift_at_start_of_file() {
+        assert_eq!(range(0, 3).shift(Loc(0), Loc(5), 0), range(0, 1));
+        assert_eq!(range(2, 4).shift(Loc(0), Loc(2), 0), range(0, 2));
+    }
 }
@@ -0,0 +1,58 @@
+//! Fixtures shared by unit tests
+
+use crate::models::*;
+
+pub fn range(from: impl Into<Loc>, until: impl Into<Loc>) -> Range {
+    Range::new(from.into(), until.into()).unwrap()
+}
+
+/// pseudo-random number in `0..n` from a linear congruential generator, to make
+/// randomized tests reproducible
+pub fn random(seed: &mut u32, n: u32) -> u32 {
+    *seed = seed.wrapping_mul(1103515245).wrapping_add(12345);
+    (*seed >> 16) % n
+}
+
+/// analysis result of `krate::path` without variables or MIR
+pub fn function(fn_id: u64, krate: &str, path: &str, span: Range) -> Function {
+    Function {
+        fn_id,
+        key: FnKey {
+            krate: krate.to_owned(),
+            def_path_hash: format!("{krate}::{path}"),
+        },
+        path: path.to_owned(),
+        signature: "fn()".to_owned(),
+        span,
+        basic_blocks: Vec::new(),
+        decls: Vec::new(),
+        closures: Vec::new(),
+        loans: Vec::new(),
+        errors: Vec::new(),
+        stale: false,
+    }
+}
+
+/// user variable alive in `lives`, which has to be dropped if `drop_range` is not empty
+pub fn user_decl(
+    local: FnLocal,
+    name: &str,
+    ty: &str,
+    span: Range,
+    lives: Vec<Range>,
+    drop_range: Vec<Range>,
+) -> MirDecl {
+    MirDecl::User {
+        local,
+        name: name.to_owned(),
+        span,
+        ty: ty.to_owned(),
+        lives,
+        place_borrows: Vec::new(),
+        drop: !drop_range.is_empty(),
+        drop_range,
+        must_live_at: Vec::new(),
+        must_live_blame: Vec::new(),
+        held_across_await: Vec::new(),
+    }
+}
@@ -1,91 +1,75 @@
 use crate::models::*;
+use tower_lsp::lsp_types;
 
 pub fn is_super_range(r1: Range, r2: Range) -> bool {
     (r1.from() < r2.from() && r2.until() <= r1.until())
         || (r1.from() <= r2.from() && r2.until() < r1.until())
 }
 
-pub fn common_range(r1: Range, r2: Range) -> Option<Range> {
-    if r2.from() < r1.from() {
-        return common_range(r2, r1);
-    }
-    if r1.until() < r2.from() {
-        return None;
-    }
-    let from = r2.from();
-    let until = r1.until().min(r2.until());
-    Range::new(from, until)
+/// Static interval tree to find values by a location inside their ranges
+///
+/// Entries are sorted by start, and each implicit subtree rooted at the middle
+/// of a slice records the largest end among its ranges.
+#[derive(Clone, Debug)]
+pub struct RangeIndex<T> {
+    entries: Vec<(Range, T)>,
+    max_until: Vec<Loc>,
 }
-
-pub fn common_ranges(ranges: &[Range]) -> Vec<Range> {
-    let mut common_ranges = Vec::new();
-    for i in 0..ranges.len() {
-        for j in i + 1..ranges.len() {
-            if let Some(common) = common_range(ranges[i], ranges[j]) {
-                common_ranges.push(common);
-            }
+impl<T> Default for RangeIndex<T> {
+    fn default() -> Self {
+        Self {
+            entries: Vec::new(),
+            max_until: Vec::new(),
         }
     }
-    eliminated_ranges(common_ranges)
 }
-
-/// merge two ranges, result is superset of two ranges
-pub fn merge_ranges(r1: Range, r2: Range) -> Option<Range> {
-    if common_range(r1, r2).is_some() || r1.until() == r2.from() || r2.until() == r1.from() {
-        let from = r1.from().min(r2.from());
-        let until = r1.until().max(r2.until());
-        Range::new(from, until)
-    } else {
-        None
+impl<T> RangeIndex<T> {
+    pub fn new(entries: impl IntoIterator<Item = (Range, T)>) -> Self {
+        let mut entries: Vec<_> = entries.into_iter().collect();
+        entries.sort_by_key(|(range, _)| range.from());
+        let mut max_until = vec![Loc(0); entries.len()];
+        Self::build(&entries, &mut max_until, 0, entries.len());
+        Self { entries, max_until }
     }
-}
-
-/// eliminate common ranges and flatten ranges
-pub fn eliminated_ranges(mut ranges: Vec<Range>) -> Vec<Range> {
-    let mut i = 0;
-    'outer: while i < ranges.len() {
-        let mut j = 0;
-        while j < ranges.len() {
-            if i != j {
-                if let Some(merged) = merge_ranges(ranges[i], ranges[j]) {
-                    ranges[i] = merged;
-                    ranges.remove(j);
-                    continue 'outer;
-                }
-            }
-            j += 1;
+    fn build(entries: &[(Range, T)], max_until: &mut [Loc], lo: usize, hi: usize) -> Loc {
+        if hi <= lo {
+            return Loc(0);
         }
-        i += 1;
+        let mid = (lo + hi) / 2;
+        let left = Self::build(entries, max_until, lo, mid);
+        let right = Self::build(entries, max_until, mid + 1, hi);
+        max_until[mid] = entries[mid].0.until().max(left).max(right);
+        max_until[mid]
     }
-    ranges
-}
-
-pub fn exclude_ranges(mut from: Vec<Range>, excludes: Vec<Range>) -> Vec<Range> {
-    let mut i = 0;
-    'outer: while i < from.len() {
-        let mut j = 0;
-        while j < excludes.len() {
-            if let Some(common) = common_range(from[i], excludes[j]) {
-                if let Some(r) = Range::new(from[i].from(), common.from() - 1) {
-                    from.push(r);
-                }
-                if let Some(r) = Range::new(common.until() + 1, from[i].until()) {
-                    from.push(r);
-                }
-                from.remove(i);
-                continue 'outer;
-            }
-            j += 1;
+    /// values whose range contains the location, including the end of the range
+    pub fn find(&self, loc: Loc) -> Vec<&T> {
+        let mut found = Vec::new();
+        self.search(loc, 0, self.entries.len(), &mut found);
+        found
+    }
+    fn search<'a>(&'a self, loc: Loc, lo: usize, hi: usize, found: &mut Vec<&'a T>) {
+        if hi <= lo || self.max_until[(lo + hi) / 2] < loc {
+            return;
         }
-        i += 1;
+        let mid = (lo + hi) / 2;
+        self.search(loc, lo, mid, found);
+        let (range, value) = &self.entries[mid];
+        // ranges in the right subtree start even later
+        if loc < range.from() {
+            return;
+        }
+        if loc <= range.until() {
+            found.push(value);
+        }
+        self.search(loc, mid + 1, hi, found);
     }
-    eliminated_ranges(from)
 }
 
 #[allow(unused)]
 pub trait MirVisitor {
     fn visit_func(&mut self, func: &Function) {}
     fn visit_decl(&mut self, decl: &MirDecl) {}
+    fn visit_closure(&mut self, closure: &MirClosure) {}
     fn visit_stmt(&mut self, stmt: &MirStatement) {}
     fn visit_term(&mut self, term: &MirTerminator) {}
 }
@@ -94,6 +78,9 @@ pub fn mir_visit(func: &Function, visitor: &mut impl MirVisitor) {
     for decl in &func.decls {
         visitor.visit_decl(decl);
     }
+    for closure in &func.closures {
+        visitor.visit_closure(closure);
+    }
     for bb in &func.basic_blocks {
         for stmt in &bb.statements {
             visitor.visit_stmt(stmt);
@@ -104,34 +91,220 @@ pub fn mir_visit(func: &Function, visitor: &mut impl MirVisitor) {
     }
 }
 
-pub fn index_to_line_char(s: &str, idx: Loc) -> (u32, u32) {
-    let mut line = 0;
-    let mut col = 0;
-    for (i, c) in s.chars().enumerate() {
-        if idx == Loc::from(i as u32) {
-            return (line, col);
+/// Unit of the `character` offset in LSP positions
+#[derive(Clone, Copy, PartialEq, Eq, Default, Debug)]
+pub enum PositionEncoding {
+    Utf8,
+    /// the default of LSP
+    #[default]
+    Utf16,
+    Utf32,
+}
+impl PositionEncoding {
+    /// number of code units of the character
+    pub fn len(self, c: char) -> u32 {
+        match self {
+            Self::Utf8 => c.len_utf8() as u32,
+            Self::Utf16 => c.len_utf16() as u32,
+            Self::Utf32 => 1,
+        }
+    }
+}
+
+/// Index of a text to convert between byte offsets, [`Loc`]s and line/character
+/// positions without scanning the whole text
+#[derive(Clone, Debug)]
+pub struct LineIndex {
+    /// byte offset of each character, followed by the length of the text
+    bytes: Vec<u32>,
+    /// UTF-16 offset of each character, followed by the length of the text
+    utf16: Vec<u32>,
+    /// index of the first character of each line
+    lines: Vec<u32>,
+}
+impl LineIndex {
+    pub fn new(s: &str) -> Self {
+        let mut bytes = Vec::with_capacity(s.len() + 1);
+        let mut utf16 = Vec::with_capacity(s.len() + 1);
+        let mut lines = vec![0];
+        let mut utf16_len = 0;
+        for (i, (byte, c)) in s.char_indices().enumerate() {
+            bytes.push(byte as u32);
+            utf16.push(utf16_len);
+            utf16_len += c.len_utf16() as u32;
+            if c == '\n' {
+                lines.push(i as u32 + 1);
+            }
+        }
+        bytes.push(s.len() as u32);
+        utf16.push(utf16_len);
+        Self {
+            bytes,
+            utf16,
+            lines,
+        }
+    }
+    /// number of characters
+    pub fn len(&self) -> u32 {
+        self.bytes.len() as u32 - 1
+    }
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+    fn offset(&self, idx: u32, encoding: PositionEncoding) -> u32 {
+        match encoding {
+            PositionEncoding::Utf8 => self.bytes[idx as usize],
+            PositionEncoding::Utf16 => self.utf16[idx as usize],
+            PositionEncoding::Utf32 => idx,
         }
-        if c == '\n' {
-            line += 1;
-            col = 0;
-        } else if c != '\r' {
-            col += 1;
+    }
+    /// byte offset of the character
+    pub fn byte(&self, idx: Loc) -> u32 {
+        self.bytes[idx.0.min(self.len()) as usize]
+    }
+    /// the character at the byte offset, or the next one if it is inside a character
+    pub fn loc(&self, byte_pos: u32) -> Loc {
+        Loc(self.bytes.partition_point(|v| *v < byte_pos) as u32)
+    }
+    pub fn line_char(&self, idx: Loc, encoding: PositionEncoding) -> (u32, u32) {
+        let idx = idx.0.min(self.len());
+        let line = self.lines.partition_point(|v| *v <= idx) - 1;
+        let start = self.lines[line];
+        (
+            line as u32,
+            self.offset(idx, encoding) - self.offset(start, encoding),
+        )
+    }
+    /// the line break ending the line, or the end of the text on the last line
+    pub fn line_end(&self, line: u32) -> Loc {
+        let end = self
+            .lines
+            .get(line as usize + 1)
+            .map(|v| v - 1)
+            .unwrap_or(self.len());
+        Loc(end)
+    }
+    pub fn lsp_position(&self, idx: Loc, encoding: PositionEncoding) -> lsp_types::Position {
+        let (line, character) = self.line_char(idx, encoding);
+        lsp_types::Position { line, character }
+    }
+    pub fn lsp_range(&self, range: Range, encoding: PositionEncoding) -> lsp_types::Range {
+        lsp_types::Range {
+            start: self.lsp_position(range.from(), encoding),
+            end: self.lsp_position(range.until(), encoding),
         }
     }
-    (0, 0)
+    /// a position inside a character or beyond the line end points to the next one
+    pub fn index(&self, line: u32, char: u32, encoding: PositionEncoding) -> Loc {
+        let Some(&start) = self.lines.get(line as usize) else {
+            return Loc(self.len());
+        };
+        let end = self.line_end(line).0;
+        let target = self.offset(start, encoding) + char;
+        let (start, end) = (start as usize, end as usize);
+        let idx = match encoding {
+            PositionEncoding::Utf8 => {
+                start + self.bytes[start..end].partition_point(|v| *v < target)
+            }
+            PositionEncoding::Utf16 => {
+                start + self.utf16[start..end].partition_point(|v| *v < target)
+            }
+            PositionEncoding::Utf32 => (target as usize).min(end),
+        };
+        Loc(idx as u32)
+    }
 }
-pub fn line_char_to_index(s: &str, mut line: u32, char: u32) -> u32 {
-    let mut col = 0;
-    for (i, c) in s.chars().enumerate() {
-        if line == 0 && col == char {
-            return i as u32;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{random, range};
+
+    const ENCODINGS: [PositionEncoding; 3] = [
+        PositionEncoding::Utf8,
+        PositionEncoding::Utf16,
+        PositionEncoding::Utf32,
+    ];
+    /// `é` takes 2 bytes, `𝕏` takes 4 bytes and 2 UTF-16 code units
+    const TEXT: &str = "aé\n𝕏b\r\nc";
+
+    #[test]
+    fn positions_round_trip() {
+        let index = LineIndex::new(TEXT);
+        assert_eq!(index.len(), 8);
+        for encoding in ENCODINGS {
+            for loc in 0..=index.len() {
+                let (line, char) = index.line_char(Loc(loc), encoding);
+                assert_eq!(index.index(line, char, encoding), Loc(loc), "{encoding:?}");
+            }
+        }
+        // `b` after `𝕏`
+        let b = [(1, 4), (1, 2), (1, 1)];
+        for (encoding, b) in ENCODINGS.into_iter().zip(b) {
+            assert_eq!(index.line_char(Loc(4), encoding), b);
+        }
+        // the line break after `é`
+        let line_end = [(0, 3), (0, 2), (0, 2)];
+        for (encoding, line_end) in ENCODINGS.into_iter().zip(line_end) {
+            assert_eq!(index.line_char(Loc(2), encoding), line_end);
         }
-        if c == '\n' && 0 < line {
-            line -= 1;
-            col = 0;
-        } else if c != '\r' {
-            col += 1;
+    }
+
+    #[test]
+    fn positions_between_characters() {
+        let index = LineIndex::new(TEXT);
+        // inside `é` and `𝕏`
+        assert_eq!(index.index(0, 2, PositionEncoding::Utf8), Loc(2));
+        assert_eq!(index.index(1, 1, PositionEncoding::Utf16), Loc(4));
+        assert_eq!(index.loc(5), Loc(4));
+        assert_eq!(index.byte(Loc(4)), 8);
+        // beyond the end of a line, the last line and the text
+        assert_eq!(index.index(0, 100, PositionEncoding::Utf16), Loc(2));
+        assert_eq!(index.index(2, 100, PositionEncoding::Utf16), Loc(8));
+        assert_eq!(index.index(5, 0, PositionEncoding::Utf16), Loc(8));
+        assert_eq!(
+            [0, 1, 2].map(|line| index.line_end(line)),
+            [Loc(2), Loc(6), Loc(8)]
+        );
+        assert_eq!(
+            index.lsp_range(Range::new(Loc(1), Loc(5)).unwrap(), PositionEncoding::Utf16),
+            lsp_types::Range::new(
+                lsp_types::Position::new(0, 1),
+                lsp_types::Position::new(1, 3)
+            )
+        );
+    }
+
+    #[test]
+    fn find_overlapping_ranges() {
+        // a function with a closure, and a function after it
+        let index = RangeIndex::new([(range(0, 20), 0), (range(5, 10), 1), (range(20, 30), 2)]);
+        assert_eq!(index.find(Loc(7)), [&0, &1]);
+        // the end is included, so both functions touching at 20 are found
+        assert_eq!(index.find(Loc(20)), [&0, &2]);
+        assert!(index.find(Loc(31)).is_empty());
+
+        // pseudo-random ranges, compared with a linear scan
+        let mut seed = 7u32;
+        let mut random = |n: u32| random(&mut seed, n);
+        for count in 0..50 {
+            let ranges: Vec<_> = (0..count)
+                .map(|i| {
+                    let from = random(100);
+                    (range(from, from + 1 + random(30)), i)
+                })
+                .collect();
+            let index = RangeIndex::new(ranges.clone());
+            for loc in 0..140 {
+                let mut found: Vec<_> = index.find(Loc(loc)).into_iter().copied().collect();
+                found.sort();
+                let expected: Vec<_> = ranges
+                    .iter()
+                    .filter(|(r, _)| r.from().0 <= loc && loc <= r.until().0)
+                    .map(|(_, i)| *i)
+                    .collect();
+                assert_eq!(found, expected);
+            }
         }
     }
-    0
 }
@@ -1,6 +1,12 @@
 import * as vscode from "vscode";
 
-import { zInfer, zLspCursorResponse, zLspRange } from "./schemas";
+import {
+  zInfer,
+  zLspCursorResponse,
+  zLspDecoration,
+  zLspFunctionOverview,
+  zLspRange,
+} from "./schemas";
 import { bootstrapRustowl } from "./bootstrap";
 import {
   LanguageClient,
@@ -24,6 +30,16 @@ export function activate(context: vscode.ExtensionContext) {
   const serverOptions: ServerOptions = lspExec;
   const clientOptions: LanguageClientOptions = {
     documentSelector: [{ scheme: "file", language: "rust" }],
+    middleware: {
+      // code lenses run the command on the server, which returns the overview
+      executeCommand: async (command, args, next) => {
+        const resp = await next(command, args);
+        if (command === "rustowl.functionOverview") {
+          await showFunctionOverview(resp);
+        }
+        return resp;
+      },
+    },
   };
 
   (async () => {
@@ -58,20 +74,21 @@ export function activate(context: vscode.ExtensionContext) {
   let imBorrowDecorationType = vscode.window.createTextEditorDecorationType({});
   let mBorrowDecorationType = vscode.window.createTextEditorDecorationType({});
   let outLiveDecorationType = vscode.window.createTextEditorDecorationType({});
+  let heldAcrossAwaitDecorationType =
+    vscode.window.createTextEditorDecorationType({});
   let emptyDecorationType = vscode.window.createTextEditorDecorationType({});
 
   // update decoration
+  const rangeToRange = (range: zInfer<typeof zLspRange>) => {
+    return new vscode.Range(
+      new vscode.Position(range.start.line, range.start.character),
+      new vscode.Position(range.end.line, range.end.character),
+    );
+  };
   const updateDecoration = (
     editor: vscode.TextEditor,
-    data: zInfer<typeof zLspCursorResponse>,
+    data: { decorations: zInfer<typeof zLspDecoration>[] },
   ) => {
-    const rangeToRange = (range: zInfer<typeof zLspRange>) => {
-      return new vscode.Range(
-        new vscode.Position(range.start.line, range.start.character),
-        new vscode.Position(range.end.line, range.end.character),
-      );
-    };
-
     const {
       underlineThickness,
       lifetimeColor,
@@ -79,6 +96,7 @@ export function activate(context: vscode.ExtensionContext) {
       immutableBorrowColor,
       mutableBorrowColor,
       outliveColor,
+      heldAcrossAwaitColor,
     } = vscode.workspace.getConfiguration("rustowl");
 
     lifetimeDecorationType = vscode.window.createTextEditorDecorationType({
@@ -96,6 +114,11 @@ export function activate(context: vscode.ExtensionContext) {
     outLiveDecorationType = vscode.window.createTextEditorDecorationType({
       textDecoration: `underline solid ${underlineThickness}px ${outliveColor}`,
     });
+    heldAcrossAwaitDecorationType = vscode.window.createTextEditorDecorationType(
+      {
+        textDecoration: `underline dashed ${underlineThickness}px ${heldAcrossAwaitColor}`,
+      },
+    );
     emptyDecorationType = vscode.window.createTextEditorDecorationType({});
 
     const lifetime: vscode.DecorationOptions[] = [];
@@ -103,6 +126,7 @@ export function activate(context: vscode.ExtensionContext) {
     const mut: vscode.DecorationOptions[] = [];
     const moveCall: vscode.DecorationOptions[] = [];
     const outlive: vscode.DecorationOptions[] = [];
+    const heldAcrossAwait: vscode.DecorationOptions[] = [];
     const messages: vscode.DecorationOptions[] = [];
     for (const deco of data.decorations) {
       const range = rangeToRange(deco.range);
@@ -119,6 +143,8 @@ export function activate(context: vscode.ExtensionContext) {
           moveCall.push({ range });
         } else if (deco.type === "shared_mut" || deco.type === "outlive") {
           outlive.push({ range });
+        } else if (deco.type === "held_across_await") {
+          heldAcrossAwait.push({ range });
         }
       }
       if ("hover_text" in deco && deco.hover_text) {
@@ -130,6 +156,7 @@ export function activate(context: vscode.ExtensionContext) {
     editor.setDecorations(mBorrowDecorationType, mut);
     editor.setDecorations(moveDecorationType, moveCall);
     editor.setDecorations(outLiveDecorationType, outlive);
+    editor.setDecorations(heldAcrossAwaitDecorationType, heldAcrossAwait);
     editor.setDecorations(emptyDecorationType, messages);
   };
   const resetDecoration = () => {
@@ -138,6 +165,7 @@ export function activate(context: vscode.ExtensionContext) {
     imBorrowDecorationType.dispose();
     mBorrowDecorationType.dispose();
     outLiveDecorationType.dispose();
+    heldAcrossAwaitDecorationType.dispose();
     emptyDecorationType.dispose();
   };
 
@@ -177,6 +205,43 @@ export function activate(context: vscode.ExtensionContext) {
     }
   };
 
+  // draw the decorations of every variable, and list the variables to jump to
+  const showFunctionOverview = async (resp: unknown) => {
+    const data = zLspFunctionOverview.safeParse(resp);
+    const editor = activeEditor;
+    if (!data.success || !editor || !data.data.function) {
+      return;
+    }
+    resetDecoration();
+    updateDecoration(editor, {
+      decorations: data.data.variables.flatMap((v) => v.decorations),
+    });
+    const lines = (spans: { start: number; end: number }[]) =>
+      spans
+        .map((v) =>
+          v.start === v.end
+            ? `${v.start + 1}`
+            : `${v.start + 1}-${v.end + 1}`,
+        )
+        .join(", ");
+    const picked = await vscode.window.showQuickPick(
+      data.data.variables.map((v) => ({
+        label: v.name,
+        description: v.ty,
+        detail: `alive on lines ${lines(v.lifetime)}`,
+        declared: rangeToRange(v.declared),
+      })),
+      { title: data.data.function },
+    );
+    if (picked) {
+      editor.selection = new vscode.Selection(
+        picked.declared.start,
+        picked.declared.start,
+      );
+      editor.revealRange(picked.declared);
+    }
+  };
+
   vscode.commands.registerCommand("rustowlHover", async (_args) => {
     if (activeEditor) {
       await rustowlHoverRequest(
@@ -67,6 +67,7 @@ export const zMirTerminator = z.union([
     type: z.literal("call"),
     destination_local_index: zIndex,
     fn_span: zRange,
+    clone: z.boolean().optional(),
   }),
   z.object({ type: z.literal("other") }),
 ]);
@@ -110,20 +111,34 @@ export const zLspType = z.union([
   z.literal("call"),
   z.literal("shared_mut"),
   z.literal("outlive"),
+  z.literal("held_across_await"),
 ]);
+export const zLspStatus = z.union([
+  z.literal("analyzing"),
+  z.literal("finished"),
+  z.literal("error"),
+]);
+export const zLspDecoration = z.object({
+  type: zLspType,
+  range: zLspRange,
+  hover_text: z.string().nullish(),
+  overlapped: z.boolean(),
+});
 export const zLspCursorResponse = z.object({
   is_analyzed: z.boolean(),
-  status: z.union([
-    z.literal("analyzing"),
-    z.literal("finished"),
-    z.literal("error"),
-  ]),
-  decorations: z
+  status: zLspStatus,
+  decorations: zLspDecoration.array(),
+});
+export const zLspFunctionOverview = z.object({
+  status: zLspStatus,
+  function: z.string().nullish(),
+  variables: z
     .object({
-      type: zLspType,
-      range: zLspRange,
-      hover_text: z.string().nullish(),
-      overlapped: z.boolean(),
+      name: z.string(),
+      ty: z.string(),
+      declared: zLspRange,
+      lifetime: z.object({ start: z.number(), end: z.number() }).array(),
+      decorations: zLspDecoration.array(),
     })
     .array(),
 });